@@ -2,7 +2,7 @@ use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Through
 
 const NUM_ENTITIES: &[usize] = &[5_000, 10_000, 50_000, 100_000 /* 500_000, 1_000_000 */];
 
-criterion_group!(name = world_benches; config = configure_criterion(); targets = world_spawn, world_spawn2, world_many_components);
+criterion_group!(name = world_benches; config = configure_criterion(); targets = world_spawn, world_spawn2, world_many_components, world_query_sparse);
 criterion_main!(world_benches);
 
 fn configure_criterion() -> Criterion {
@@ -41,6 +41,13 @@ struct L(usize);
 #[derive(Copy, Clone, Component, bevy_ecs::component::Component)]
 struct X<A: Send + Sync + 'static, B: Send + Sync + 'static>(A, B);
 
+#[derive(Copy, Clone, Component)]
+#[component(sparse)]
+struct SparseMarker;
+#[derive(Copy, Clone, bevy_ecs::component::Component)]
+#[component(storage = "SparseSet")]
+struct BevySparseMarker;
+
 // TODO: big number of components / different bigger numbers of components
 
 /// Span a number of entities and change their component configuration
@@ -384,3 +391,51 @@ pub fn world_many_components(c: &mut Criterion) {
     }
     group.finish()
 }
+
+/// Iterate a query that is driven by a rarely used sparse component
+pub fn world_query_sparse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("query_sparse");
+    for &entity_count in NUM_ENTITIES {
+        group.throughput(Throughput::Elements(entity_count as u64));
+        group.bench_function(BenchmarkId::new("pulz", entity_count), |bencher| {
+            let mut res = Resources::new();
+            {
+                let mut world = res.world_mut();
+                for i in 0..entity_count {
+                    let mut e = world.spawn();
+                    e.insert(A(i));
+                    if i % 100 == 0 {
+                        e.insert(SparseMarker);
+                    }
+                }
+            }
+            let mut query = res.query::<(&SparseMarker, &A)>();
+            bencher.iter(|| {
+                let mut sum = 0;
+                for (_, a) in query.iter() {
+                    sum += a.0;
+                }
+                criterion::black_box(sum)
+            });
+        });
+        group.bench_function(BenchmarkId::new("bevy", entity_count), |bencher| {
+            use bevy_ecs::world::World;
+            let mut world = World::new();
+            for i in 0..entity_count {
+                let mut e = world.spawn(A(i));
+                if i % 100 == 0 {
+                    e.insert(BevySparseMarker);
+                }
+            }
+            let mut query = world.query::<(&BevySparseMarker, &A)>();
+            bencher.iter(|| {
+                let mut sum = 0;
+                for (_, a) in query.iter(&world) {
+                    sum += a.0;
+                }
+                criterion::black_box(sum)
+            });
+        });
+    }
+    group.finish()
+}
@@ -235,7 +235,7 @@ impl BitSet {
         let end = match range.end_bound() {
             std::ops::Bound::Included(i) => *i,
             std::ops::Bound::Excluded(i) => (*i).saturating_sub(1),
-            std::ops::Bound::Unbounded => 0,
+            std::ops::Bound::Unbounded => usize::MAX,
         };
         (start, end)
     }
@@ -617,4 +617,22 @@ mod tests {
         assert_eq!(Some(1337), iter.next());
         assert_eq!(None, iter.next());
     }
+
+    #[test]
+    fn test_retain() {
+        let mut subject = BitSet::new();
+
+        assert!(subject.insert(1));
+        assert!(subject.insert(2));
+        assert!(subject.insert(63));
+        assert!(subject.insert(1337));
+
+        subject.retain(.., |i| i % 2 == 1);
+
+        let mut iter = subject.iter();
+        assert_eq!(Some(1), iter.next());
+        assert_eq!(Some(63), iter.next());
+        assert_eq!(Some(1337), iter.next());
+        assert_eq!(None, iter.next());
+    }
 }
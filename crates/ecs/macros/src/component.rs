@@ -1,21 +1,51 @@
 use darling::{
+    ast::NestedMeta,
     util::{Flag, SpannedValue},
-    Error, FromDeriveInput, Result,
+    Error, FromDeriveInput, FromMeta, Result,
 };
 use proc_macro2::TokenStream;
-use quote::quote;
-use syn::{parse_quote, DeriveInput, Path};
+use quote::{format_ident, quote};
+use syn::{parse_quote, Data, DeriveInput, Expr, Fields, Ident, Meta, Path};
 
 use crate::utils::resolve_crate;
 
 pub fn derive_component(input: DeriveInput) -> Result<TokenStream> {
     let args = ComponentStructArgs::from_derive_input(&input)?;
 
-    let ident = input.ident;
+    let ident = &input.ident;
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     let crate_ecs = resolve_crate("pulz-ecs")?;
 
+    let mut register = Vec::new();
+    if args.clone.is_present() {
+        register.push(quote! {
+            details.set_clonable::<Self>();
+        });
+    }
+    for (path, constructor) in &args.requires.0 {
+        let constructor = match constructor {
+            Some(expr) => quote!(|| #expr),
+            None => quote!(<#path as ::core::default::Default>::default),
+        };
+        register.push(quote! {
+            details.add_required::<#path>(#constructor);
+        });
+    }
+    let register = if register.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            #[inline]
+            fn register(details: &mut #crate_ecs::component::ComponentDetails) {
+                #(#register)*
+            }
+        }
+    };
+    if args.soa.is_present() {
+        return derive_soa(&input, &crate_ecs, register);
+    }
+
     let storage: Path = if let Some(storage) = &*args.storage {
         if let Some(single_ident) = storage.get_ident() {
             parse_quote!(#crate_ecs::storage::#single_ident)
@@ -28,12 +58,164 @@ pub fn derive_component(input: DeriveInput) -> Result<TokenStream> {
         parse_quote!(#crate_ecs::storage::ArchetypeStorage)
     };
     let mut storage: syn::Type = parse_quote!(#storage<Self>);
+    if args.immutable.is_present() {
+        storage = parse_quote!(#crate_ecs::storage::Immutable<#storage>);
+    }
     if args.tracked.is_present() {
         storage = parse_quote!(#crate_ecs::storage::Tracked<#storage>);
     }
     Ok(quote! {
         impl #impl_generics #crate_ecs::component::Component for #ident #ty_generics #where_clause {
             type Storage = #storage;
+            #register
+        }
+    })
+}
+
+/// Generates the columns, the proxies and the query params of a struct-of-arrays
+/// component.
+fn derive_soa(input: &DeriveInput, crate_ecs: &Path, register: TokenStream) -> Result<TokenStream> {
+    let ident = &input.ident;
+    let vis = &input.vis;
+    if !input.generics.params.is_empty() {
+        const MSG: &str = "`soa` doesn't support generic components";
+        return Err(Error::custom(MSG).with_span(&input.generics));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) if !fields.named.is_empty() => &fields.named,
+            _ => {
+                const MSG: &str = "`soa` requires a struct with named fields";
+                return Err(Error::custom(MSG).with_span(ident));
+            }
+        },
+        _ => {
+            const MSG: &str = "`soa` is only supported for structs";
+            return Err(Error::custom(MSG).with_span(ident));
+        }
+    };
+    let names: Vec<&Ident> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let field_vis: Vec<_> = fields.iter().map(|f| &f.vis).collect();
+    let types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+    let first = names[0];
+
+    let columns_ident = format_ident!("{}Columns", ident);
+    let ref_ident = format_ident!("{}Ref", ident);
+    let mut_ident = format_ident!("{}Mut", ident);
+    let slices_ident = format_ident!("{}Slices", ident);
+    let slices_mut_ident = format_ident!("{}SlicesMut", ident);
+    let ref_doc = format!("Shared references to the fields of a [`{ident}`].");
+    let mut_doc = format!("Mutable references to the fields of a [`{ident}`].");
+    let slices_doc = format!("The columns of the fields of [`{ident}`] components.");
+    let slices_mut_doc = format!("The mutable columns of the fields of [`{ident}`] components.");
+
+    Ok(quote! {
+        #[doc(hidden)]
+        #[derive(Default)]
+        #vis struct #columns_ident {
+            #(#names: ::std::vec::Vec<#types>,)*
+        }
+
+        #[doc = #ref_doc]
+        #[derive(Clone, Copy)]
+        #vis struct #ref_ident<'a> {
+            #(#field_vis #names: &'a #types,)*
+        }
+
+        #[doc = #mut_doc]
+        #vis struct #mut_ident<'a> {
+            #(#field_vis #names: &'a mut #types,)*
+        }
+
+        #[doc = #slices_doc]
+        #[derive(Clone, Copy)]
+        #vis struct #slices_ident<'a> {
+            #(#field_vis #names: &'a [#types],)*
+        }
+
+        #[doc = #slices_mut_doc]
+        #vis struct #slices_mut_ident<'a> {
+            #(#field_vis #names: &'a mut [#types],)*
+        }
+
+        impl #crate_ecs::soa::SoaComponent for #ident {
+            type Columns = #columns_ident;
+            type Ref<'__a> = #ref_ident<'__a>;
+            type Mut<'__a> = #mut_ident<'__a>;
+            type Slices<'__a> = #slices_ident<'__a>;
+            type SlicesMut<'__a> = #slices_mut_ident<'__a>;
+
+            #[inline]
+            fn len(__columns: &Self::Columns) -> usize {
+                __columns.#first.len()
+            }
+
+            #[inline]
+            fn push(__columns: &mut Self::Columns, __value: Self) {
+                let Self { #(#names,)* } = __value;
+                #(__columns.#names.push(#names);)*
+            }
+
+            #[inline]
+            fn swap_remove(__columns: &mut Self::Columns, __index: usize) -> Self {
+                Self {
+                    #(#names: __columns.#names.swap_remove(__index),)*
+                }
+            }
+
+            #[inline]
+            fn replace(__columns: &mut Self::Columns, __index: usize, __value: Self) -> Self {
+                let Self { #(#names,)* } = __value;
+                Self {
+                    #(#names: ::core::mem::replace(&mut __columns.#names[__index], #names),)*
+                }
+            }
+
+            #[inline]
+            fn get(__columns: &Self::Columns, __index: usize) -> Self::Ref<'_> {
+                #ref_ident {
+                    #(#names: &__columns.#names[__index],)*
+                }
+            }
+
+            #[inline]
+            fn get_mut(__columns: &mut Self::Columns, __index: usize) -> Self::Mut<'_> {
+                #mut_ident {
+                    #(#names: &mut __columns.#names[__index],)*
+                }
+            }
+
+            #[inline]
+            fn slices(__columns: &Self::Columns) -> Self::Slices<'_> {
+                #slices_ident {
+                    #(#names: &__columns.#names,)*
+                }
+            }
+
+            #[inline]
+            fn slices_mut(__columns: &mut Self::Columns) -> Self::SlicesMut<'_> {
+                #slices_mut_ident {
+                    #(#names: &mut __columns.#names,)*
+                }
+            }
+        }
+
+        impl #crate_ecs::component::Component for #ident {
+            type Storage = #crate_ecs::soa::SoaStorage<Self>;
+            #register
+        }
+
+        impl #crate_ecs::query::QueryParam for #ref_ident<'_> {
+            type State = #crate_ecs::soa::QrySoaState<#ident, false>;
+            type Fetch<'__w> = #crate_ecs::soa::QrySoaRefFetch<'__w, #ident>;
+        }
+
+        // SAFETY: only shared access
+        unsafe impl #crate_ecs::query::ReadOnlyQueryParam for #ref_ident<'_> {}
+
+        impl #crate_ecs::query::QueryParam for #mut_ident<'_> {
+            type State = #crate_ecs::soa::QrySoaState<#ident, true>;
+            type Fetch<'__w> = #crate_ecs::soa::QrySoaMutFetch<'__w, #ident>;
         }
     })
 }
@@ -48,15 +230,55 @@ pub fn derive_component(input: DeriveInput) -> Result<TokenStream> {
 pub struct ComponentStructArgs {
     sparse: Flag,
     tracked: Flag,
+    immutable: Flag,
+    clone: Flag,
+    /// stores every field in a separate column (see `SoaStorage`)
+    soa: Flag,
+    requires: Requires,
     storage: SpannedValue<Option<Path>>,
 }
 
+/// `requires(A, B = expr)`: required components, with an optional constructor
+/// (`Default` otherwise).
+#[derive(Default)]
+struct Requires(Vec<(Path, Option<Expr>)>);
+
+impl FromMeta for Requires {
+    fn from_list(items: &[NestedMeta]) -> Result<Self> {
+        let mut requires = Vec::with_capacity(items.len());
+        for item in items {
+            match item {
+                NestedMeta::Meta(Meta::Path(path)) => requires.push((path.clone(), None)),
+                NestedMeta::Meta(Meta::NameValue(nv)) => {
+                    requires.push((nv.path.clone(), Some(nv.value.clone())))
+                }
+                _ => {
+                    const MSG: &str = "expected a component type, or `Component = constructor`";
+                    return Err(Error::custom(MSG).with_span(item));
+                }
+            }
+        }
+        Ok(Self(requires))
+    }
+}
+
 impl ComponentStructArgs {
     fn validate(self) -> Result<Self> {
         if self.sparse.is_present() && self.storage.is_some() {
             const MSG: &str = "either provide `sparse` or `storage`, but not both!";
             return Err(Error::custom(MSG));
         }
+        if self.soa.is_present()
+            && (self.sparse.is_present()
+                || self.storage.is_some()
+                || self.tracked.is_present()
+                || self.immutable.is_present()
+                || self.clone.is_present())
+        {
+            const MSG: &str =
+                "`soa` can't be combined with `sparse`, `storage`, `tracked`, `immutable` or `clone`!";
+            return Err(Error::custom(MSG));
+        }
         Ok(self)
     }
 }
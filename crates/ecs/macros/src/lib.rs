@@ -5,6 +5,7 @@ use syn::{parse_macro_input, DeriveInput};
 mod utils;
 mod bundle;
 mod component;
+mod query_data;
 
 #[proc_macro_derive(Component, attributes(component))]
 pub fn derive_component(input: TokenStream) -> TokenStream {
@@ -21,3 +22,11 @@ pub fn derive_bundle(input: TokenStream) -> TokenStream {
         .unwrap_or_else(|err| err.write_errors())
         .into()
 }
+
+#[proc_macro_derive(QueryData, attributes(query))]
+pub fn derive_query_data(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    query_data::derive_query_data(input)
+        .unwrap_or_else(|err| err.write_errors())
+        .into()
+}
@@ -0,0 +1,155 @@
+use darling::{
+    ast::{Data, Style},
+    util::Ignored,
+    Error, FromDeriveInput, FromField, Result,
+};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_quote, DeriveInput, GenericParam, Ident, Type};
+
+use crate::utils::{into_static_type, resolve_crate};
+
+pub fn derive_query_data(input: DeriveInput) -> Result<TokenStream> {
+    let args = QueryDataStructArgs::from_derive_input(&input)?;
+
+    let mut lifetime = None;
+    for param in &input.generics.params {
+        match param {
+            GenericParam::Lifetime(param) if lifetime.is_none() => {
+                lifetime = Some(param.lifetime.clone());
+            }
+            _ => {
+                const MSG: &str = "QueryData only supports a single lifetime parameter";
+                return Err(Error::custom(MSG).with_span(param));
+            }
+        }
+    }
+
+    let Data::Struct(fields) = args.data else {
+        unreachable!("only structs are supported")
+    };
+    if fields.style != Style::Struct {
+        const MSG: &str = "QueryData only supports structs with named fields";
+        return Err(Error::custom(MSG).with_span(&input.ident));
+    }
+
+    let crate_ecs = resolve_crate("pulz-ecs")?;
+
+    let ident = input.ident;
+    let vis = input.vis;
+    let fetch_ident = format_ident!("{}Fetch", ident);
+    let field_idents: Vec<&Ident> = fields
+        .fields
+        .iter()
+        .map(|f| f.ident.as_ref().unwrap())
+        .collect();
+    let field_types: Vec<&Type> = fields.fields.iter().map(|f| &f.ty).collect();
+
+    // the query-params of the fields, independent of the lifetime of the struct
+    let param_types: Vec<Type> = field_types
+        .iter()
+        .map(|ty| into_static_type(ty, lifetime.as_ref()))
+        .collect();
+
+    let mut inner: Type = parse_quote!((#(#param_types,)*));
+    if let Some(filter) = &args.filter {
+        let filter = into_static_type(filter, None);
+        inner = parse_quote!(#crate_ecs::query::With<#filter, #inner>);
+    }
+    if let Some(without) = &args.without {
+        let without = into_static_type(without, None);
+        inner = parse_quote!(#crate_ecs::query::Without<#without, #inner>);
+    }
+
+    let (impl_generics, ty_generics, item_generics) = match &lifetime {
+        Some(lifetime) => (quote!(<#lifetime>), quote!(<#lifetime>), quote!(<'__a>)),
+        None => (quote!(), quote!(), quote!()),
+    };
+
+    Ok(quote! {
+        impl #impl_generics #crate_ecs::query::QueryParam for #ident #ty_generics {
+            type State = <#inner as #crate_ecs::query::QueryParam>::State;
+            type Fetch<'__w> = #fetch_ident<'__w>;
+        }
+
+        // SAFETY: delegates to all fields
+        unsafe impl #impl_generics #crate_ecs::query::ReadOnlyQueryParam for #ident #ty_generics
+        where
+            (#(#field_types,)*): #crate_ecs::query::ReadOnlyQueryParam,
+        {
+        }
+
+        #[doc(hidden)]
+        #vis struct #fetch_ident<'__w>(<#inner as #crate_ecs::query::QueryParam>::Fetch<'__w>);
+
+        impl<'__w> #crate_ecs::query::QueryParamFetch<'__w> for #fetch_ident<'__w> {
+            type State = <#inner as #crate_ecs::query::QueryParam>::State;
+            type Item<'__a> = #ident #item_generics where Self: '__a;
+
+            #[inline]
+            fn fetch(res: &'__w #crate_ecs::resource::ResourcesSend, state: &Self::State) -> Self {
+                Self(#crate_ecs::query::QueryParamFetch::fetch(res, state))
+            }
+
+            #[inline]
+            fn set_archetype(
+                &mut self,
+                state: &Self::State,
+                archetype: &#crate_ecs::archetype::Archetype,
+            ) {
+                self.0.set_archetype(state, archetype)
+            }
+
+            #[inline]
+            fn get(
+                &mut self,
+                archetype: &#crate_ecs::archetype::Archetype,
+                index: usize,
+            ) -> Self::Item<'_> {
+                let (#(#field_idents,)*) = self.0.get(archetype, index);
+                #ident { #(#field_idents,)* }
+            }
+
+            #[inline]
+            fn matches_entity(
+                &self,
+                archetype: &#crate_ecs::archetype::Archetype,
+                index: usize,
+            ) -> bool {
+                self.0.matches_entity(archetype, index)
+            }
+
+            #[inline]
+            fn sparse_len(&self) -> Option<usize> {
+                self.0.sparse_len()
+            }
+
+            #[inline]
+            fn collect_sparse_entities(&self, entities: &mut Vec<#crate_ecs::entity::Entity>) {
+                self.0.collect_sparse_entities(entities)
+            }
+        }
+    })
+}
+
+#[derive(FromDeriveInput)]
+#[darling(
+    attributes(query),
+    forward_attrs(allow, doc, cfg),
+    supports(struct_named)
+)]
+pub struct QueryDataStructArgs {
+    data: Data<Ignored, QueryDataFieldArgs>,
+    /// components that are required (like `With`), but not fetched
+    #[darling(default)]
+    filter: Option<Type>,
+    /// components that are excluded (like `Without`)
+    #[darling(default)]
+    without: Option<Type>,
+}
+
+#[derive(FromField)]
+pub struct QueryDataFieldArgs {
+    ident: Option<Ident>,
+    ty: Type,
+}
@@ -1,6 +1,7 @@
-use proc_macro2::{Ident, Span};
+use proc_macro2::{Group, Ident, Span, TokenStream, TokenTree};
 use proc_macro_crate::FoundCrate;
-use syn::{Error, Path, Result, Token};
+use quote::ToTokens;
+use syn::{Error, Lifetime, Path, Result, Token, Type};
 
 pub fn resolve_crate(name: &str) -> Result<Path> {
     match proc_macro_crate::crate_name(name).map_err(|e| Error::new(Span::call_site(), e))? {
@@ -12,3 +13,47 @@ pub fn resolve_crate(name: &str) -> Result<Path> {
         }
     }
 }
+
+/// Replaces the given lifetime, `'_` and elided reference-lifetimes with `'static`.
+pub fn into_static_type(ty: &Type, lifetime: Option<&Lifetime>) -> Type {
+    let tokens = into_static_tokens(ty.to_token_stream(), lifetime);
+    syn::parse2(tokens).expect("valid type")
+}
+
+fn into_static_tokens(tokens: TokenStream, lifetime: Option<&Lifetime>) -> TokenStream {
+    let static_lifetime = Lifetime::new("'static", Span::call_site());
+    let mut result = TokenStream::new();
+    let mut iter = tokens.into_iter().peekable();
+    while let Some(token) = iter.next() {
+        match token {
+            TokenTree::Group(group) => {
+                let stream = into_static_tokens(group.stream(), lifetime);
+                let mut new_group = Group::new(group.delimiter(), stream);
+                new_group.set_span(group.span());
+                result.extend([TokenTree::Group(new_group)]);
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '\'' => {
+                let Some(TokenTree::Ident(ident)) = iter.next() else {
+                    unreachable!("lifetime without ident");
+                };
+                if ident == "_" || lifetime.is_some_and(|l| l.ident == ident) {
+                    static_lifetime.to_tokens(&mut result);
+                } else {
+                    Lifetime {
+                        apostrophe: punct.span(),
+                        ident,
+                    }
+                    .to_tokens(&mut result);
+                }
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '&' => {
+                result.extend([TokenTree::Punct(punct)]);
+                if !matches!(iter.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '\'') {
+                    static_lifetime.to_tokens(&mut result);
+                }
+            }
+            token => result.extend([token]),
+        }
+    }
+    result
+}
@@ -201,6 +201,12 @@ impl ArchetypeSet {
     pub fn iter(&self) -> ArchetypeSetIter<'_> {
         ArchetypeSetIter(self.0.iter())
     }
+
+    /// only retain the archetypes from other (intersection)
+    #[inline]
+    pub fn retain_set(&mut self, other: &Self) {
+        self.0.retain_bitset(&other.0)
+    }
 }
 
 impl Default for ArchetypeSet {
@@ -0,0 +1,367 @@
+use std::ops::Range;
+
+use crate::{
+    archetype::ArchetypeId,
+    component::{Component, ComponentId, RequiredComponent},
+    entity::{Entity, EntityLocation},
+    entity_ref::storage_mut_dyn,
+    get_or_init_component,
+    group::sync_groups,
+    resource::{ResourceId, Resources},
+    storage::{swap_remove_and_push_many, Storage},
+    world::WorldMut,
+    WorldInner,
+};
+
+impl WorldMut<'_> {
+    /// Inserts the component returned by `f` into all the given entities.
+    ///
+    /// The entities are grouped by their archetype, so the destination
+    /// archetype is only computed once per group, and the components are moved
+    /// in bulk. Existing components are replaced. Entities that don't exist
+    /// are skipped. Missing required components are inserted as well.
+    pub fn insert_for_each<T, F>(&mut self, entities: impl IntoIterator<Item = Entity>, mut f: F)
+    where
+        T: Component,
+        F: FnMut(Entity) -> T,
+    {
+        let (storage_id, component_id) =
+            get_or_init_component::<T>(self.res, &mut self.world.components);
+        let world: &mut WorldInner = &mut self.world;
+        let required = world.components.components[component_id.offset()]
+            .required
+            .clone()
+            .unwrap_or_default();
+        // components owned by groups
+        let grouped: Vec<ComponentId> = std::iter::once(component_id.untyped())
+            .chain(required.iter().map(|r| r.id))
+            .filter(|&id| world.components.components[id.offset()].group.is_some())
+            .collect();
+        let groups = group_by_archetype(world, entities);
+        for (archetype_id, indices) in groups.iter() {
+            let archetype = &world.archetypes[archetype_id];
+            let mut new_components = archetype.components.clone();
+            if !T::Storage::SPARSE {
+                new_components.insert(component_id);
+            }
+            for required in &required {
+                if world.components.components[required.id.offset()].archetype_component {
+                    new_components.insert(required.id);
+                }
+            }
+            if new_components == archetype.components {
+                // no structural change
+                let storage = get_storage_mut::<T>(self.res, storage_id);
+                for &index in indices {
+                    let entity = archetype.entities[index];
+                    let value = f(entity);
+                    if !T::Storage::TAG {
+                        storage.insert(entity, value);
+                        storage.flush_replace(entity, archetype_id, index);
+                    }
+                }
+                for &index in indices {
+                    let entity = archetype.entities[index];
+                    insert_required(self.res, world, &required, entity, archetype_id, index);
+                    sync_groups(self.res, &world.components, &grouped, entity);
+                }
+                continue;
+            }
+
+            let new_archetype_id = world.archetypes.get_or_insert(new_components);
+            let new_start = move_entities(self.res, world, archetype_id, indices, new_archetype_id);
+
+            let storage = get_storage_mut::<T>(self.res, storage_id);
+            let new_archetype = &world.archetypes[new_archetype_id];
+            for (index, &entity) in new_archetype.entities.iter().enumerate().skip(new_start) {
+                let value = f(entity);
+                if T::Storage::TAG {
+                    continue;
+                }
+                storage.insert(entity, value);
+                if !T::Storage::SPARSE {
+                    let result = storage.flush_push(entity, new_archetype_id);
+                    debug_assert_eq!(Some(index), result);
+                }
+            }
+            for (index, &entity) in new_archetype.entities.iter().enumerate().skip(new_start) {
+                insert_required(self.res, world, &required, entity, new_archetype_id, index);
+                sync_groups(self.res, &world.components, &grouped, entity);
+            }
+        }
+    }
+
+    /// Removes the component `T` from all the given entities.
+    ///
+    /// Like [`insert_for_each`](Self::insert_for_each), the entities are
+    /// grouped by their archetype. Entities that don't exist or don't have
+    /// the component are skipped.
+    pub fn remove_for_each<T>(&mut self, entities: impl IntoIterator<Item = Entity>)
+    where
+        T: Component,
+    {
+        let Some(component_id) = self.world.components.id::<T>() else {
+            return;
+        };
+        let storage_id: ResourceId<T::Storage> = self
+            .world
+            .components
+            .get(component_id)
+            .expect("component")
+            .storage_id
+            .typed();
+        let world: &mut WorldInner = &mut self.world;
+        let groups = group_by_archetype(world, entities);
+        for (archetype_id, indices) in groups.iter() {
+            let archetype = &world.archetypes[archetype_id];
+            let storage = get_storage_mut::<T>(self.res, storage_id);
+            if T::Storage::SPARSE {
+                // no structural change
+                for &index in indices {
+                    storage.swap_remove(archetype.entities[index], archetype_id, index);
+                }
+                for &index in indices {
+                    let entity = archetype.entities[index];
+                    sync_groups(
+                        self.res,
+                        &world.components,
+                        &[component_id.untyped()],
+                        entity,
+                    );
+                }
+                continue;
+            }
+            if !archetype.components.contains(component_id) {
+                continue;
+            }
+
+            // remove the components in the same order as the entities are moved
+            if !T::Storage::TAG {
+                for &index in indices {
+                    storage.swap_remove(archetype.entities[index], archetype_id, index);
+                }
+            }
+            let mut new_components = archetype.components.clone();
+            new_components.remove(component_id);
+            let new_archetype_id = world.archetypes.get_or_insert(new_components);
+            move_entities(self.res, world, archetype_id, indices, new_archetype_id);
+        }
+    }
+}
+
+/// Inserts the required components, that the entity doesn't have.
+fn insert_required(
+    res: &mut Resources,
+    world: &WorldInner,
+    required: &[RequiredComponent],
+    entity: Entity,
+    archetype_id: ArchetypeId,
+    index: usize,
+) {
+    for required in required {
+        let component = &world.components.components[required.id.offset()];
+        if component.tag {
+            // already part of the archetype
+            continue;
+        }
+        let storage = storage_mut_dyn(res, component).expect("storage");
+        if storage.contains(entity, archetype_id, index) {
+            continue;
+        }
+        (required.insert)(res, component, entity);
+        let storage = storage_mut_dyn(res, component).expect("storage");
+        if component.archetype_component {
+            let result = storage.flush_push(entity, archetype_id);
+            debug_assert_eq!(Some(index), result);
+        } else {
+            storage.flush_replace(entity, archetype_id, index);
+        }
+    }
+}
+
+/// The indices of entities, grouped by archetype, and sorted in descending
+/// order inside of each group.
+struct ArchetypeGroups {
+    groups: Vec<(ArchetypeId, Range<usize>)>,
+    indices: Vec<usize>,
+}
+
+impl ArchetypeGroups {
+    fn iter(&self) -> impl Iterator<Item = (ArchetypeId, &[usize])> + '_ {
+        self.groups
+            .iter()
+            .map(|(archetype_id, range)| (*archetype_id, &self.indices[range.clone()]))
+    }
+}
+
+fn group_by_archetype(
+    world: &WorldInner,
+    entities: impl IntoIterator<Item = Entity>,
+) -> ArchetypeGroups {
+    let mut locations: Vec<_> = entities
+        .into_iter()
+        .filter_map(|entity| world.entities.get(entity))
+        .filter(|location| location.is_occupied())
+        .map(|location| (location.archetype_id, location.index))
+        .collect();
+    locations.sort_unstable_by(|a, b| a.0.index().cmp(&b.0.index()).then(b.1.cmp(&a.1)));
+    locations.dedup();
+
+    let mut groups: Vec<(ArchetypeId, Range<usize>)> = Vec::new();
+    let mut indices = Vec::with_capacity(locations.len());
+    for (archetype_id, index) in locations {
+        match groups.last_mut() {
+            Some((id, range)) if *id == archetype_id => range.end += 1,
+            _ => groups.push((archetype_id, indices.len()..indices.len() + 1)),
+        }
+        indices.push(index);
+    }
+    ArchetypeGroups { groups, indices }
+}
+
+/// Moves the entities at the given indices (sorted in descending order) and
+/// their components to the end of another archetype. Components that are not
+/// part of the new archetype must be removed by the caller before.
+///
+/// Returns the index of the first moved entity in the new archetype.
+fn move_entities(
+    res: &mut Resources,
+    world: &mut WorldInner,
+    old_archetype_id: ArchetypeId,
+    indices: &[usize],
+    new_archetype_id: ArchetypeId,
+) -> usize {
+    let [old_archetype, new_archetype] = world
+        .archetypes
+        .get_disjoint_array_mut([old_archetype_id, new_archetype_id])
+        .expect("unable to find archetypes");
+    let new_start = new_archetype.len();
+
+    for component in old_archetype.components.iter_details(&world.components) {
+        if !component.tag && new_archetype.components.contains(component.id()) {
+            let storage = storage_mut_dyn(res, component).expect("storage");
+            storage.swap_remove_and_insert_many(old_archetype_id, indices, new_archetype_id);
+        }
+    }
+    swap_remove_and_push_many(
+        &mut old_archetype.entities,
+        indices,
+        &mut new_archetype.entities,
+    );
+
+    // update the locations of swapped and moved entities
+    let first_swapped = indices.last().copied().unwrap_or(0);
+    for (index, &entity) in old_archetype
+        .entities
+        .iter()
+        .enumerate()
+        .skip(first_swapped)
+    {
+        world
+            .entities
+            .get_mut(entity)
+            .expect("swapped entity")
+            .index = index;
+    }
+    for (index, &entity) in new_archetype.entities.iter().enumerate().skip(new_start) {
+        *world.entities.get_mut(entity).expect("moved entity") = EntityLocation {
+            archetype_id: new_archetype_id,
+            index,
+        };
+    }
+    new_start
+}
+
+fn get_storage_mut<T>(res: &mut Resources, storage_id: ResourceId<T::Storage>) -> &mut T::Storage
+where
+    T: Component,
+{
+    res.get_mut_id(storage_id).expect("storage")
+}
+
+#[cfg(test)]
+mod test {
+    use pulz_schedule::resource::Resources;
+
+    use crate::{component::Component, entity::Entity, world::WorldMut, WorldExt};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    struct A(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    struct B(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(sparse)]
+    struct C(usize);
+
+    fn get<T: Component + Copy>(world: &WorldMut<'_>, entity: Entity) -> Option<T> {
+        world.entity(entity)?.borrow::<T>().as_deref().copied()
+    }
+
+    #[test]
+    fn test_insert_remove_for_each() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let entities: Vec<Entity> = (0..100)
+            .map(|i| match i % 3 {
+                0 => world.spawn().insert(A(i)).id(),
+                1 => world.spawn().insert(A(i)).insert(C(i)).id(),
+                _ => world.spawn().id(),
+            })
+            .collect();
+        let despawned = world.spawn().id();
+        world.despawn(despawned);
+
+        // every second entity (and a duplicate and a despawned entity)
+        let selected = entities
+            .iter()
+            .copied()
+            .step_by(2)
+            .chain([entities[0], despawned]);
+        world.insert_for_each(selected, |e| {
+            B(entities.iter().position(|&x| x == e).unwrap())
+        });
+        for (i, &entity) in entities.iter().enumerate() {
+            assert_eq!((i % 2 == 0).then_some(B(i)), get::<B>(&world, entity));
+            assert_eq!((i % 3 != 2).then_some(A(i)), get::<A>(&world, entity));
+            assert_eq!((i % 3 == 1).then_some(C(i)), get::<C>(&world, entity));
+        }
+
+        // replace existing and insert sparse components
+        world.insert_for_each(entities[..10].iter().copied(), |_| B(1000));
+        world.insert_for_each(entities[..10].iter().copied(), |_| C(1000));
+        for (i, &entity) in entities.iter().enumerate() {
+            let b = if i < 10 {
+                Some(B(1000))
+            } else {
+                (i % 2 == 0).then_some(B(i))
+            };
+            let c = if i < 10 {
+                Some(C(1000))
+            } else {
+                (i % 3 == 1).then_some(C(i))
+            };
+            assert_eq!(b, get::<B>(&world, entity));
+            assert_eq!(c, get::<C>(&world, entity));
+        }
+
+        // remove from all entities (whole columns) and some entities
+        world.remove_for_each::<A>(entities.iter().copied());
+        world.remove_for_each::<C>(entities[..10].iter().copied());
+        world.remove_for_each::<B>(entities[50..].iter().copied());
+        for (i, &entity) in entities.iter().enumerate() {
+            let b = if i < 10 {
+                Some(B(1000))
+            } else {
+                (i % 2 == 0 && i < 50).then_some(B(i))
+            };
+            assert_eq!(None, get::<A>(&world, entity));
+            assert_eq!(b, get::<B>(&world, entity));
+            assert_eq!(
+                (i >= 10 && i % 3 == 1).then_some(C(i)),
+                get::<C>(&world, entity)
+            );
+        }
+    }
+}
@@ -5,10 +5,13 @@ use std::{
     hash::Hash,
     marker::PhantomData,
     ops::Range,
+    sync::Arc,
 };
 
 use crate::{
-    resource::{Res, ResMut, ResourceId},
+    archetype::ArchetypeId,
+    entity::Entity,
+    resource::{Res, ResMut, ResourceId, Resources},
     storage::{AnyStorage, Storage},
 };
 
@@ -21,6 +24,14 @@ use pulz_schedule::meta::any_cast_mut_unchecked;
 
 pub trait Component: Send + Sync + 'static {
     type Storage: Storage<Component = Self>;
+
+    /// Called once, when the component is registered in a world.
+    ///
+    /// Can be used to register optional capabilities of the component, like
+    /// [`ComponentDetails::set_clonable`] or
+    /// [`ComponentDetails::add_required`].
+    #[inline]
+    fn register(_details: &mut ComponentDetails) {}
 }
 
 pub trait Bundle {}
@@ -97,8 +108,66 @@ pub struct ComponentDetails {
     name: Cow<'static, str>,
     type_id: TypeId,
     pub(crate) archetype_component: bool,
+    pub(crate) tag: bool,
     pub(crate) storage_id: ResourceId,
     pub(crate) storage_downcast_mut: unsafe fn(&mut dyn Any) -> &mut dyn AnyStorage,
+    pub(crate) clone_fn: Option<CloneFn>,
+    requires: Vec<RequiresComponent>,
+    /// The resolved (transitive) required components, or `None` while they
+    /// are resolved.
+    pub(crate) required: Option<Vec<RequiredComponent>>,
+    /// Updates the membership of an entity in the group owning this
+    /// component.
+    pub(crate) group: Option<Arc<SyncGroupFn>>,
+}
+
+/// Stages a new value of the required component for an entity.
+pub(crate) type InsertRequiredFn = dyn Fn(&mut Resources, &ComponentDetails, Entity) + Send + Sync;
+
+/// Moves an entity into or out of a group, depending on whether it has all
+/// components of the group.
+pub(crate) type SyncGroupFn = dyn Fn(&mut Resources, Entity) + Send + Sync;
+
+/// A required component, like it was declared with
+/// [`ComponentDetails::add_required`].
+struct RequiresComponent {
+    init: fn(&mut Resources, &mut Components) -> ComponentId,
+    insert: Arc<InsertRequiredFn>,
+}
+
+/// A resolved required component.
+#[derive(Clone)]
+pub(crate) struct RequiredComponent {
+    pub(crate) id: ComponentId,
+    pub(crate) insert: Arc<InsertRequiredFn>,
+}
+
+/// Clones the component of an entity (`source`, `source_archetype`,
+/// `source_index`), and stages the clone for the entity `target` (see
+/// [`Storage::insert`]). Returns `false`, when there was nothing to clone.
+pub(crate) type CloneFn =
+    fn(&mut Resources, &ComponentDetails, Entity, ArchetypeId, usize, Entity) -> bool;
+
+fn clone_component<T>(
+    res: &mut Resources,
+    details: &ComponentDetails,
+    source: Entity,
+    source_archetype: ArchetypeId,
+    source_index: usize,
+    target: Entity,
+) -> bool
+where
+    T: Component + Clone,
+{
+    let storage_id: ResourceId<T::Storage> = details.storage_id.typed();
+    let Some(storage) = res.get_mut_id(storage_id) else {
+        return false;
+    };
+    let Some(value) = storage.get(source, source_archetype, source_index).cloned() else {
+        return false;
+    };
+    Storage::insert(storage, target, value);
+    true
 }
 
 impl ComponentDetails {
@@ -116,6 +185,76 @@ impl ComponentDetails {
     pub fn type_id(&self) -> TypeId {
         self.type_id
     }
+
+    /// Returns `true`, when the component is a zero-sized tag, that is only
+    /// tracked by the archetypes (see [`Storage::TAG`]).
+    #[inline]
+    pub fn is_tag(&self) -> bool {
+        self.tag
+    }
+
+    /// Returns `true`, when the component can be cloned by
+    /// [`WorldMut::clone_entity`](crate::world::WorldMut::clone_entity).
+    #[inline]
+    pub fn is_clonable(&self) -> bool {
+        self.clone_fn.is_some()
+    }
+
+    /// Registers the `Clone` implementation of the component, so it can be
+    /// cloned by [`WorldMut::clone_entity`](crate::world::WorldMut::clone_entity).
+    ///
+    /// # Panics
+    /// Panics when `T` is not the type of this component, or when the
+    /// component is not stored as a whole (see [`Storage::BORROWABLE`]).
+    pub fn set_clonable<T>(&mut self)
+    where
+        T: Component + Clone,
+    {
+        assert_eq!(TypeId::of::<T>(), self.type_id, "wrong component type");
+        assert!(
+            <T::Storage as Storage>::BORROWABLE,
+            "component {} is not stored as a whole and can't be cloned",
+            self.name()
+        );
+        self.clone_fn = Some(clone_component::<T>);
+    }
+
+    /// Returns the components, that are inserted together with this
+    /// component (including indirectly required components).
+    pub fn required_components(&self) -> impl Iterator<Item = ComponentId> + '_ {
+        self.required.iter().flatten().map(|r| r.id)
+    }
+
+    /// Declares, that the component `R` is required by this component.
+    ///
+    /// When this component is inserted into an entity without `R`, then a
+    /// value created by `constructor` is inserted as well. The requirements
+    /// of `R` are also inserted (transitively).
+    ///
+    /// The requirements are resolved, when the component is registered.
+    ///
+    /// # Panics
+    /// Registering the component panics, when the requirements have a cycle.
+    pub fn add_required<R>(&mut self, constructor: fn() -> R)
+    where
+        R: Component,
+    {
+        self.requires.push(RequiresComponent {
+            init: |res, components| {
+                crate::get_or_init_component::<R>(res, components)
+                    .1
+                    .untyped()
+            },
+            insert: Arc::new(move |res, details, entity| {
+                if details.tag {
+                    return;
+                }
+                let storage_id: ResourceId<R::Storage> = details.storage_id.typed();
+                let storage = res.get_mut_id(storage_id).expect("storage");
+                Storage::insert(storage, entity, constructor());
+            }),
+        });
+    }
 }
 
 pub struct Components {
@@ -173,14 +312,24 @@ impl Components {
             Entry::Vacant(entry) => {
                 let index = components.len();
                 let id = ComponentId(index, PhantomData);
-                components.push(ComponentDetails {
+                let mut details = ComponentDetails {
                     id,
                     name: Cow::Borrowed(std::any::type_name::<T>()),
                     type_id,
                     archetype_component: !<T::Storage as Storage>::SPARSE,
+                    tag: <T::Storage as Storage>::TAG,
                     storage_id: storage_id.untyped().typed(),
                     storage_downcast_mut: any_cast_mut_unchecked::<dyn AnyStorage, T::Storage>,
-                });
+                    clone_fn: None,
+                    requires: Vec::new(),
+                    required: None,
+                    group: None,
+                };
+                T::register(&mut details);
+                if details.requires.is_empty() {
+                    details.required = Some(Vec::new());
+                }
+                components.push(details);
                 entry.insert(id);
                 Ok(id.typed())
             }
@@ -188,6 +337,53 @@ impl Components {
         }
     }
 
+    /// Resolves the required components of a newly inserted component, and
+    /// initializes them.
+    ///
+    /// # Panics
+    /// Panics when the requirements have a cycle.
+    pub(crate) fn resolve_required(&mut self, res: &mut Resources, id: ComponentId) {
+        let index = id.offset();
+        if self.components[index].required.is_some() {
+            return;
+        }
+        let requires: Vec<_> = self.components[index]
+            .requires
+            .iter()
+            .map(|r| (r.init, r.insert.clone()))
+            .collect();
+        // direct requirements first, so their constructors take precedence
+        let mut required: Vec<RequiredComponent> = Vec::new();
+        for (init, insert) in requires {
+            let required_id = init(res, self);
+            if self.components[required_id.offset()].required.is_none() {
+                panic!(
+                    "cycle in the required components of `{}`: `{}` requires it",
+                    self.components[index].name,
+                    self.components[required_id.offset()].name
+                );
+            }
+            if !required.iter().any(|r| r.id == required_id) {
+                required.push(RequiredComponent {
+                    id: required_id,
+                    insert,
+                });
+            }
+        }
+        for i in 0..required.len() {
+            let indirect = self.components[required[i].id.offset()]
+                .required
+                .as_ref()
+                .expect("resolved");
+            for r in indirect {
+                if !required.iter().any(|x| x.id == r.id) {
+                    required.push(r.clone());
+                }
+            }
+        }
+        self.components[index].required = Some(required);
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
         self.components.len()
@@ -400,3 +596,108 @@ impl<T> Default for ComponentMap<T> {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use pulz_schedule::resource::Resources;
+
+    use super::Component;
+    use crate::{entity::Entity, world::WorldMut, WorldExt};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Component)]
+    struct Transform(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    enum Visibility {
+        Visible,
+        Hidden,
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Component)]
+    #[component(requires(Transform, Visibility = Visibility::Visible))]
+    struct Player;
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(sparse, requires(Player, Visibility = Visibility::Hidden))]
+    struct Ghost;
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Component)]
+    #[component(requires(Egg))]
+    struct Chicken;
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Component)]
+    #[component(requires(Chicken))]
+    struct Egg;
+
+    fn get<T: Component + Copy>(world: &WorldMut<'_>, entity: Entity) -> Option<T> {
+        world.entity(entity)?.borrow::<T>().as_deref().copied()
+    }
+
+    #[test]
+    fn test_required_components() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let a = world.spawn().insert(Player).id();
+        let b = world.spawn().insert(Transform(2)).insert(Player).id();
+        let c = world.spawn().insert(Ghost).id();
+        let d = world.spawn().insert(Ghost).insert(Transform(4)).id();
+
+        assert_eq!(Some(Transform(0)), get(&world, a));
+        assert_eq!(Some(Visibility::Visible), get(&world, a));
+        assert_eq!(Some(Transform(2)), get(&world, b));
+        assert_eq!(Some(Visibility::Visible), get(&world, b));
+        // direct requirements take precedence over transitive requirements
+        assert_eq!(Some(Player), get(&world, c));
+        assert_eq!(Some(Transform(0)), get(&world, c));
+        assert_eq!(Some(Visibility::Hidden), get(&world, c));
+        assert_eq!(Some(Transform(4)), get(&world, d));
+        assert_eq!(
+            world.entity(a).unwrap().archetype().id,
+            world.entity(d).unwrap().archetype().id
+        );
+
+        let player = world.components().id::<Player>().unwrap();
+        let mut required: Vec<_> = world
+            .components()
+            .get(player)
+            .unwrap()
+            .required_components()
+            .collect();
+        required.sort();
+        let mut expected = vec![
+            world.components().id::<Transform>().unwrap().untyped(),
+            world.components().id::<Visibility>().unwrap().untyped(),
+        ];
+        expected.sort();
+        assert_eq!(expected, required);
+
+        // existing components are kept
+        world.entity_mut(b).unwrap().insert(Visibility::Hidden);
+        world.entity_mut(b).unwrap().insert(Player);
+        assert_eq!(Some(Visibility::Hidden), get(&world, b));
+    }
+
+    #[test]
+    fn test_required_components_for_each() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let a = world.spawn().id();
+        let b = world.spawn().insert(Transform(2)).id();
+        world.insert_for_each([a, b], |_| Ghost);
+        for e in [a, b] {
+            assert_eq!(Some(Ghost), get(&world, e));
+            assert_eq!(Some(Player), get(&world, e));
+            assert_eq!(Some(Visibility::Hidden), get(&world, e));
+        }
+        assert_eq!(Some(Transform(0)), get(&world, a));
+        assert_eq!(Some(Transform(2)), get(&world, b));
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle in the required components")]
+    fn test_required_components_cycle() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        world.init::<Chicken>();
+    }
+}
@@ -1,7 +1,12 @@
+use std::collections::HashSet;
+
 use slotmap::{new_key_type, SlotMap};
 
 use crate::archetype::ArchetypeId;
-pub use crate::entity_ref::{EntityMut, EntityRef};
+pub use crate::{
+    entity_ref::{EntityComponents, EntityMut, EntityRef},
+    ref_counted::{StrongEntity, WeakEntity},
+};
 
 new_key_type! {
     pub struct Entity;
@@ -84,6 +89,62 @@ impl Entities {
     }
 }
 
+/// A list of entities without duplicates.
+///
+/// Used for fetching exclusive query items of multiple entities at once
+/// (see [`Query::iter_many_mut`](crate::query::Query::iter_many_mut)).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UniqueEntities(Vec<Entity>);
+
+impl UniqueEntities {
+    #[inline]
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Creates a list from the given entities, keeping their order.
+    ///
+    /// Returns the first duplicate entity as error.
+    pub fn from_entities(entities: impl IntoIterator<Item = Entity>) -> Result<Self, Entity> {
+        let entities = entities.into_iter();
+        let mut seen = HashSet::with_capacity(entities.size_hint().0);
+        let mut result = Vec::with_capacity(entities.size_hint().0);
+        for entity in entities {
+            if !seen.insert(entity) {
+                return Err(entity);
+            }
+            result.push(entity);
+        }
+        Ok(Self(result))
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[Entity] {
+        &self.0
+    }
+
+    #[inline]
+    pub fn into_vec(self) -> Vec<Entity> {
+        self.0
+    }
+}
+
+impl std::ops::Deref for UniqueEntities {
+    type Target = [Entity];
+    #[inline]
+    fn deref(&self) -> &[Entity] {
+        &self.0
+    }
+}
+
+impl FromIterator<Entity> for UniqueEntities {
+    /// Collects the given entities, skipping duplicates.
+    fn from_iter<I: IntoIterator<Item = Entity>>(iter: I) -> Self {
+        let mut seen = HashSet::new();
+        Self(iter.into_iter().filter(|e| seen.insert(*e)).collect())
+    }
+}
+
 impl std::ops::Index<Entity> for Entities {
     type Output = EntityLocation;
     #[inline]
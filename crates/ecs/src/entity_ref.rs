@@ -1,12 +1,14 @@
-use std::any::TypeId;
+use std::{any::TypeId, pin::Pin};
 
 use crate::{
     archetype::{Archetype, ArchetypeId},
     component::{Component, ComponentDetails, ComponentId, Ref, RefMut},
     entity::{Entity, EntityLocation},
-    get_or_init_component,
+    get_or_init_component, group, prefab,
+    query::{QueryItem, QueryParam, QueryParamFetch, QueryParamState, ReadOnlyQueryParam},
+    relation::{Relation, RelationStorage},
     resource::{Res, ResMut, ResourceId, Resources},
-    storage::{AnyStorage, Storage},
+    storage::{get_component, get_component_mut, AnyStorage, Storage},
     world::{World, WorldMut},
     WorldInner,
 };
@@ -86,11 +88,77 @@ impl<'w> EntityRef<'w> {
         T: Component,
     {
         let component = &self.world.components.get(component_id)?;
+        assert_borrowable::<T>(component);
         let storage = storage::<T>(self.res, component)?;
+        let archetype = &self.world.archetypes[self.location.archetype_id];
         Ref::filter_map(storage, |storage| {
-            storage.get(self.entity, self.location.archetype_id, self.location.index)
+            get_component(
+                storage,
+                component,
+                self.entity,
+                archetype,
+                self.location.index,
+            )
         })
     }
+
+    /// Returns a shared reference to the given component of this entity, or
+    /// the component inherited from its prefab (see
+    /// [`WorldMut::instantiate`]).
+    pub fn borrow_inherited<T>(&self) -> Option<Ref<'_, T>>
+    where
+        T: Component,
+    {
+        borrow_inherited::<T>(self.res, self.world, self.entity, self.location)
+    }
+
+    /// Returns the items of the read-only query `Q` for this entity, or `None`
+    /// when this entity doesn't match the query (or a component of the query
+    /// was never registered).
+    pub fn get_components<Q>(&self) -> Option<EntityComponents<'w, Q>>
+    where
+        Q: ReadOnlyQueryParam,
+    {
+        if !Q::State::is_registered(&self.world.components) {
+            return None;
+        }
+        let state = Q::State::init(self.res, &self.world.components);
+        let archetype = &self.world.archetypes[self.location.archetype_id];
+        if !state.matches_archetype(archetype) {
+            return None;
+        }
+        let mut fetch = <Q::Fetch<'w> as QueryParamFetch<'w>>::fetch(self.res.as_send(), &state);
+        fetch.set_archetype(&state, archetype);
+        if !fetch.matches_entity(archetype, self.location.index) {
+            return None;
+        }
+        Some(EntityComponents {
+            fetch,
+            archetype,
+            index: self.location.index,
+        })
+    }
+}
+
+/// The items of a read-only query for a single entity (see
+/// [`EntityRef::get_components`]).
+pub struct EntityComponents<'w, Q>
+where
+    Q: QueryParam + 'w,
+{
+    fetch: Q::Fetch<'w>,
+    archetype: &'w Archetype,
+    index: usize,
+}
+
+impl<'w, Q> EntityComponents<'w, Q>
+where
+    Q: QueryParam + 'w,
+{
+    #[inline]
+    pub fn get(&mut self) -> QueryItem<'w, '_, Q> {
+        self.fetch.get(self.archetype, self.index)
+    }
 }
 
 /// An exclusive reference to a entity of a world.
@@ -176,13 +244,25 @@ impl<'w> EntityMut<'w> {
         T: Component,
     {
         let component = &self.world.components.get(component_id)?;
+        assert_borrowable::<T>(component);
         let storage = storage::<T>(self.res, component)?;
+        let archetype = &self.world.archetypes[self.location.archetype_id];
         Ref::filter_map(storage, |storage| {
-            storage.get(self.entity, self.location.archetype_id, self.location.index)
+            get_component(
+                storage,
+                component,
+                self.entity,
+                archetype,
+                self.location.index,
+            )
         })
     }
 
     /// Returns an exclusive reference to the given component of this entity, if not already borrowed
+    ///
+    /// # Panics
+    /// Panics when the component is immutable (see
+    /// [`Immutable`](crate::storage::Immutable)).
     #[inline]
     pub fn borrow_mut<T>(&self) -> Option<RefMut<'_, T>>
     where
@@ -199,12 +279,103 @@ impl<'w> EntityMut<'w> {
         T: Component,
     {
         let component = &self.world.components.get(component_id)?;
+        assert_mutable::<T>(component);
         let storage = storage_mut::<T>(self.res, component)?;
+        let archetype = &self.world.archetypes[self.location.archetype_id];
         RefMut::filter_map(storage, |storage| {
-            storage.get_mut(self.entity, self.location.archetype_id, self.location.index)
+            get_component_mut(
+                storage,
+                component,
+                self.entity,
+                archetype,
+                self.location.index,
+            )
         })
     }
 
+    /// Returns a shared reference to the given component of this entity, or
+    /// the component inherited from its prefab (see
+    /// [`WorldMut::instantiate`]).
+    pub fn borrow_inherited<T>(&self) -> Option<Ref<'_, T>>
+    where
+        T: Component,
+    {
+        borrow_inherited::<T>(self.res, self.world, self.entity, self.location)
+    }
+
+    /// Returns an exclusive reference to the given component of this entity.
+    ///
+    /// Unlike [`borrow_mut`](Self::borrow_mut), the component is borrowed
+    /// statically through this `EntityMut`, instead of borrowing the whole
+    /// storage at runtime.
+    ///
+    /// When this entity doesn't have the component, but inherits a clonable
+    /// component from its prefab (see [`WorldMut::instantiate`]), then a copy
+    /// of the inherited component is inserted first (copy-on-write).
+    ///
+    /// # Panics
+    /// Panics when the component is immutable (see
+    /// [`Immutable`](crate::storage::Immutable)).
+    #[inline]
+    pub fn get_mut<T>(&mut self) -> Option<&mut T>
+    where
+        T: Component,
+    {
+        let component_id = self.world.components.id::<T>()?;
+        self.get_mut_by_id::<T>(component_id)
+    }
+
+    /// Returns an exclusive reference to the given component of this entity.
+    pub fn get_mut_by_id<T>(&mut self, component_id: ComponentId<T>) -> Option<&mut T>
+    where
+        T: Component,
+    {
+        if self.world.tmp_removed.contains(component_id) {
+            return None;
+        }
+        let component = &self.world.components.get(component_id)?;
+        assert_mutable::<T>(component);
+        if component.clone_fn.is_some()
+            && !self.world.tmp_inserted.contains(component_id)
+            && !self.contains_id(component_id)
+            && self.stage_inherited(component_id.untyped())
+        {
+            // copy-on-write
+            self.flush();
+        }
+        let component = &self.world.components.get(component_id)?;
+        let storage_id: ResourceId<T::Storage> = component.storage_id.typed();
+        let storage = self.res.get_mut_id(storage_id)?;
+        let archetype = &self.world.archetypes[self.location.archetype_id];
+        get_component_mut(
+            storage,
+            component,
+            self.entity,
+            archetype,
+            self.location.index,
+        )
+    }
+
+    /// Returns a pinned exclusive reference to the given component of this
+    /// entity.
+    ///
+    /// Returns `None`, when the component is not stored in a storage that
+    /// never moves its components (like
+    /// [`PinnedStorage`](crate::storage::PinnedStorage)).
+    pub fn get_pin_mut<T>(&mut self) -> Option<Pin<&mut T>>
+    where
+        T: Component,
+    {
+        let component_id = self.world.components.id::<T>()?;
+        if self.world.tmp_removed.contains(component_id) {
+            return None;
+        }
+        let component = &self.world.components.get(component_id)?;
+        let storage_id: ResourceId<T::Storage> = component.storage_id.typed();
+        let storage = self.res.get_mut_id(storage_id)?;
+        storage.get_pin_mut(self.entity, self.location.archetype_id, self.location.index)
+    }
+
     #[inline]
     pub fn insert<T>(&mut self, value: T) -> &mut Self
     where
@@ -221,13 +392,45 @@ impl<'w> EntityMut<'w> {
         self.world.tmp_removed.remove(component_id);
         self.world.tmp_inserted.insert(component_id);
         let component = &self.world.components.get(component_id).expect("component");
-        {
+        if !component.tag {
             let mut storage = storage_mut::<T>(self.res, component).expect("storage");
             storage.insert(self.entity, value);
         }
+        self.insert_required(component_id.untyped());
         self
     }
 
+    /// Stages the required components of a component, that this entity
+    /// doesn't have (and that are not already staged).
+    fn insert_required(&mut self, component_id: ComponentId) {
+        let world: &mut WorldInner = self.world;
+        let Some(required) = &world.components.components[component_id.offset()].required else {
+            return;
+        };
+        for required in required {
+            if world.tmp_inserted.contains(required.id) {
+                continue;
+            }
+            let component = &world.components.components[required.id.offset()];
+            // components that will be removed are replaced
+            if !world.tmp_removed.remove(required.id) {
+                let contains = if component.tag {
+                    world.archetypes[self.location.archetype_id]
+                        .components
+                        .contains(required.id)
+                } else {
+                    let storage = storage_mut_dyn(self.res, component).expect("storage");
+                    storage.contains(self.entity, self.location.archetype_id, self.location.index)
+                };
+                if contains {
+                    continue;
+                }
+            }
+            (required.insert)(self.res, component, self.entity);
+            world.tmp_inserted.insert(required.id);
+        }
+    }
+
     #[inline]
     pub fn remove<T>(&mut self) -> &mut Self
     where
@@ -245,6 +448,136 @@ impl<'w> EntityMut<'w> {
         self
     }
 
+    /// Inserts a copy of the component `T`, that this entity inherits from its
+    /// prefab (see [`WorldMut::instantiate`]), so it can be changed without
+    /// affecting the prefab and its other instances.
+    ///
+    /// Does nothing, when this entity already has its own component.
+    pub fn override_inherited<T>(&mut self) -> &mut Self
+    where
+        T: Component + Clone,
+    {
+        let Some(component_id) = self.world.components.id::<T>() else {
+            return self;
+        };
+        if self.world.tmp_inserted.contains(component_id)
+            || (!self.world.tmp_removed.contains(component_id) && self.contains_id(component_id))
+        {
+            return self;
+        }
+        let value = {
+            let component = self.world.components.get(component_id).expect("component");
+            let storage = storage::<T>(self.res, component).expect("storage");
+            prefab::borrow_relations(self.res, &self.world.components).and_then(|relations| {
+                prefab::inherited(&*storage, component, &relations, self.world, self.entity)
+                    .cloned()
+            })
+        };
+        if let Some(value) = value {
+            self.insert_by_id(component_id, value);
+        }
+        self
+    }
+
+    /// Inserts copies of all clonable components (see
+    /// [`ComponentDetails::set_clonable`]), that this entity inherits from its
+    /// prefab, so later changes of the prefab don't propagate to it.
+    pub fn override_all_inherited(&mut self) -> &mut Self {
+        let clonable: Vec<ComponentId> = self
+            .world
+            .components
+            .components
+            .iter()
+            .filter(|component| component.clone_fn.is_some())
+            .map(ComponentDetails::id)
+            .collect();
+        for component_id in clonable {
+            if self.world.tmp_inserted.contains(component_id) {
+                continue;
+            }
+            let component = &self.world.components.components[component_id.offset()];
+            let contains = if self.world.tmp_removed.contains(component_id) {
+                false
+            } else if component.tag {
+                self.archetype().components.contains(component_id)
+            } else {
+                storage_mut_dyn(self.res, component).map_or(false, |storage| {
+                    storage.contains(self.entity, self.location.archetype_id, self.location.index)
+                })
+            };
+            if !contains {
+                self.stage_inherited(component_id);
+            }
+        }
+        self
+    }
+
+    /// Stages a clone of the component, that this entity inherits from its
+    /// prefab. Returns `false`, when the component is not clonable or not
+    /// inherited.
+    fn stage_inherited(&mut self, component_id: ComponentId) -> bool {
+        let component = &self.world.components.components[component_id.offset()];
+        let Some(clone_fn) = component.clone_fn else {
+            return false;
+        };
+        let mut current = self.entity;
+        for _ in 0..prefab::MAX_DEPTH {
+            let Some(prefab) = prefab::prefab_of(self.res, &self.world.components, current) else {
+                return false;
+            };
+            let Some(location) = self.world.entities.get(prefab) else {
+                return false;
+            };
+            let cloned = if component.tag {
+                // only tracked by the archetype
+                self.world.archetypes[location.archetype_id]
+                    .components
+                    .contains(component_id)
+            } else {
+                clone_fn(
+                    self.res,
+                    component,
+                    prefab,
+                    location.archetype_id,
+                    location.index,
+                    self.entity,
+                )
+            };
+            if cloned {
+                self.world.tmp_removed.remove(component_id);
+                self.world.tmp_inserted.insert(component_id);
+                self.insert_required(component_id);
+                return true;
+            }
+            current = prefab;
+        }
+        false
+    }
+
+    /// Adds `target` to the targets of the relation `R` of this entity.
+    pub fn relate<R: 'static>(&mut self, target: Entity) -> &mut Self {
+        let (storage_id, component_id) =
+            get_or_init_component::<Relation<R>>(self.res, &mut self.world.components);
+        let storage = self.res.get_mut_id(storage_id).expect("storage");
+        if self.world.tmp_removed.remove(component_id) {
+            storage.remove(self.entity);
+        }
+        storage.add(self.entity, target);
+        self
+    }
+
+    /// Removes `target` from the targets of the relation `R` of this entity.
+    /// The relation is removed, when it has no targets left.
+    pub fn unrelate<R: 'static>(&mut self, target: Entity) -> &mut Self {
+        if let Some(component_id) = self.world.components.id::<Relation<R>>() {
+            let component = self.world.components.get(component_id).expect("component");
+            let storage_id: ResourceId<RelationStorage<R>> = component.storage_id.typed();
+            let storage = self.res.get_mut_id(storage_id).expect("storage");
+            storage.remove_target(self.entity, target);
+        }
+        self
+    }
+
     pub fn clear(&mut self) -> &mut Self {
         // clear open operations
         self.world.tmp_inserted.clear();
@@ -265,14 +598,21 @@ impl<'w> EntityMut<'w> {
         self.world.tmp_inserted.clear();
 
         let location = self.location;
+        let archetype = &self.world.archetypes[location.archetype_id];
 
         // remove components and track removal
         // TODO: track_removed
         for component in &self.world.components.components {
             let id = component.id();
+            if component.tag {
+                if archetype.components.contains(id) {
+                    self.world.tmp_removed.insert(id);
+                }
+                continue;
+            }
             if let Some(storage) = storage_mut_dyn(self.res, component) {
                 // remove
-                if storage.swap_remove(self.entity, location.archetype_id, location.index) {
+                if storage.despawn(self.entity, location.archetype_id, location.index) {
                     // track
                     self.world.tmp_removed.insert(id);
                 }
@@ -301,8 +641,15 @@ impl<'w> EntityMut<'w> {
     }
 }
 
-impl<'w> Drop for EntityMut<'w> {
-    fn drop(&mut self) {
+impl EntityMut<'_> {
+    /// Applies the staged changes, so the entity can be changed further.
+    fn flush(&mut self) {
+        self.apply_staged();
+        self.world.tmp_removed.clear();
+        self.world.tmp_inserted.clear();
+    }
+
+    fn apply_staged(&mut self) {
         let old = self.location;
         let old_archetype = self
             .world
@@ -312,10 +659,26 @@ impl<'w> Drop for EntityMut<'w> {
 
         let mut needs_update_archetype = false;
 
+        // groups of the changed components
+        let groups: Vec<ComponentId> = self
+            .world
+            .tmp_removed
+            .iter_details(&self.world.components)
+            .chain(self.world.tmp_inserted.iter_details(&self.world.components))
+            .filter(|component| component.group.is_some())
+            .map(ComponentDetails::id)
+            .collect();
+
         // remove components
         // TODO: track_removed
         self.world.tmp_removed.retain(|index| {
             let component = &self.world.components.components[index];
+            if component.tag {
+                // tags are only tracked by the archetype
+                let contained = old_archetype.components.contains(component.id());
+                needs_update_archetype |= contained;
+                return contained;
+            }
             if let Some(storage) = storage_mut_dyn(self.res, component) {
                 if storage.swap_remove(self.entity, old.archetype_id, old.index) {
                     if component.archetype_component {
@@ -330,8 +693,13 @@ impl<'w> Drop for EntityMut<'w> {
         // replace existing components
         self.world.tmp_inserted.retain(|index| {
             let component = &self.world.components.components[index];
+            if component.tag {
+                let contained = old_archetype.components.contains(component.id());
+                needs_update_archetype |= !contained;
+                return !contained;
+            }
             let storage = storage_mut_dyn(self.res, component).expect("storage");
-            if !storage.flush_replace(old.archetype_id, old.index) {
+            if !storage.flush_replace(self.entity, old.archetype_id, old.index) {
                 if component.archetype_component {
                     needs_update_archetype = true;
                 }
@@ -340,6 +708,8 @@ impl<'w> Drop for EntityMut<'w> {
             false
         });
 
+        group::sync_groups(self.res, &self.world.components, &groups, self.entity);
+
         if !needs_update_archetype {
             return;
         }
@@ -368,7 +738,7 @@ impl<'w> Drop for EntityMut<'w> {
             .iter_details(&self.world.components)
         {
             let id = component.id();
-            if new_archetype.components.contains(id) {
+            if !component.tag && new_archetype.components.contains(id) {
                 let storage = storage_mut_dyn(self.res, component).expect("storage");
                 let result =
                     storage.swap_remove_and_insert(old.archetype_id, old.index, new_archetype_id);
@@ -384,9 +754,12 @@ impl<'w> Drop for EntityMut<'w> {
 
         // insert new ones
         for component in self.world.tmp_inserted.iter_details(&self.world.components) {
+            if component.tag {
+                continue;
+            }
             let id = component.id();
             let storage = storage_mut_dyn(self.res, component).expect("storage");
-            let result = storage.flush_push(new_archetype_id);
+            let result = storage.flush_push(self.entity, new_archetype_id);
             assert_eq!(
                 Some(new_index),
                 result,
@@ -418,6 +791,56 @@ impl<'w> Drop for EntityMut<'w> {
     }
 }
 
+impl Drop for EntityMut<'_> {
+    fn drop(&mut self) {
+        self.apply_staged();
+    }
+}
+
+fn borrow_inherited<'a, T>(
+    res: &'a Resources,
+    world: &WorldInner,
+    entity: Entity,
+    location: EntityLocation,
+) -> Option<Ref<'a, T>>
+where
+    T: Component,
+{
+    let component_id = world.components.id::<T>()?;
+    let component = world.components.get(component_id)?;
+    assert_borrowable::<T>(component);
+    let storage = storage::<T>(res, component)?;
+    let relations = prefab::borrow_relations(res, &world.components);
+    let archetype = &world.archetypes[location.archetype_id];
+    Ref::filter_map(storage, |storage| {
+        get_component(storage, component, entity, archetype, location.index)
+            .or_else(|| prefab::inherited(storage, component, relations.as_deref()?, world, entity))
+    })
+}
+
+fn assert_borrowable<T>(component: &ComponentDetails)
+where
+    T: Component,
+{
+    assert!(
+        <T::Storage as Storage>::BORROWABLE,
+        "component {} is not stored as a whole and can't be borrowed",
+        component.name()
+    );
+}
+
+fn assert_mutable<T>(component: &ComponentDetails)
+where
+    T: Component,
+{
+    assert_borrowable::<T>(component);
+    assert!(
+        <T::Storage as Storage>::MUTABLE,
+        "component {} is immutable and can't be borrowed mutably",
+        component.name()
+    );
+}
+
 fn storage<'a, T>(res: &'a Resources, component: &ComponentDetails) -> Option<Res<'a, T::Storage>>
 where
     T: Component,
@@ -439,7 +862,7 @@ where
     res.borrow_res_mut_id(storage_id)
 }
 
-fn storage_mut_dyn<'a>(
+pub fn storage_mut_dyn<'a>(
     res: &'a mut Resources,
     component: &ComponentDetails,
 ) -> Option<&'a mut dyn AnyStorage> {
@@ -470,13 +893,143 @@ impl WorldMut<'_> {
     /// Returns an exclusive reference ([`EntityMut`]) to the entity with the
     /// given id.
     pub fn entity_mut(&mut self, entity: Entity) -> Option<EntityMut<'_>> {
-        let Some(location) = self.world.entities.get_mut(entity) else {
-            return None;
-        };
-        let location = *location;
+        let location = *self.world.entities.get_mut(entity)?;
         Some(EntityMut::new(self.res, &mut self.world, entity, location))
     }
 
+    /// Returns exclusive references to the component `T` of multiple
+    /// entities at once.
+    ///
+    /// Returns `None`, when one of the entities doesn't exist or doesn't have
+    /// the component, or when an entity is given more than once.
+    ///
+    /// # Panics
+    /// Panics when the component is immutable (see
+    /// [`Immutable`](crate::storage::Immutable)).
+    pub fn get_many_components_mut<T, const N: usize>(
+        &mut self,
+        entities: [Entity; N],
+    ) -> Option<[&mut T; N]>
+    where
+        T: Component,
+    {
+        for (i, entity) in entities.iter().enumerate() {
+            if entities[..i].contains(entity) {
+                return None;
+            }
+        }
+        let component_id = self.world.components.id::<T>()?;
+        let component = &self.world.components.get(component_id)?;
+        assert_mutable::<T>(component);
+        let storage_id: ResourceId<T::Storage> = component.storage_id.typed();
+        let storage = self.res.get_mut_id(storage_id)?;
+        let mut components = [std::ptr::null_mut::<T>(); N];
+        for (ptr, entity) in components.iter_mut().zip(entities) {
+            let location = self.world.entities.get(entity)?;
+            let archetype = &self.world.archetypes[location.archetype_id];
+            *ptr = get_component_mut(storage, component, entity, archetype, location.index)?;
+        }
+        // SAFETY: the entities are unique, so the components don't alias
+        Some(components.map(|component| unsafe { &mut *component }))
+    }
+
+    /// Spawns a copy of the given entity with clones of all its clonable
+    /// components (see [`ComponentDetails::set_clonable`]). Other components
+    /// are skipped (see
+    /// [`clone_entity_with_skipped`](Self::clone_entity_with_skipped)).
+    ///
+    /// The copy is pushed directly into the archetype of the source entity
+    /// (or the archetype with only the cloned components).
+    ///
+    /// Returns `None`, when the entity doesn't exist.
+    #[inline]
+    pub fn clone_entity(&mut self, entity: Entity) -> Option<Entity> {
+        self.clone_entity_with_skipped(entity, &mut Vec::new())
+    }
+
+    /// Like [`clone_entity`](Self::clone_entity), but also collects the
+    /// components of the source entity, that were not cloned, into `skipped`.
+    pub fn clone_entity_with_skipped(
+        &mut self,
+        entity: Entity,
+        skipped: &mut Vec<ComponentId>,
+    ) -> Option<Entity> {
+        let location = self.world.entities.get(entity)?;
+        let world: &mut WorldInner = &mut self.world;
+        let components = &world.components;
+        let clone = world.entities.create();
+
+        // stage the clones, the archetype only gets the cloned components
+        let source_components = &world.archetypes[location.archetype_id].components;
+        let mut target_components = source_components.clone();
+        for component in &components.components {
+            let in_archetype = source_components.contains(component.id());
+            let cloned = if component.tag {
+                // only tracked by the archetype
+                component.is_clonable()
+            } else if let Some(clone_fn) = component.clone_fn {
+                clone_fn(
+                    self.res,
+                    component,
+                    entity,
+                    location.archetype_id,
+                    location.index,
+                    clone,
+                )
+            } else {
+                false
+            };
+            if cloned {
+                continue;
+            }
+            if in_archetype {
+                target_components.remove(component.id());
+                skipped.push(component.id());
+            } else if !component.archetype_component
+                && storage_mut_dyn(self.res, component).map_or(false, |s| {
+                    s.contains(entity, location.archetype_id, location.index)
+                })
+            {
+                skipped.push(component.id());
+            }
+        }
+        let target_archetype_id = if target_components == *source_components {
+            location.archetype_id
+        } else {
+            world.archetypes.get_or_insert(target_components)
+        };
+
+        let target_archetype = world
+            .archetypes
+            .get_mut(target_archetype_id)
+            .expect("archetype");
+        let index = target_archetype.len();
+        target_archetype.entities.push(clone);
+        *world.entities.get_mut(clone).expect("entity") = EntityLocation {
+            archetype_id: target_archetype_id,
+            index,
+        };
+
+        // one column push per component
+        let target_archetype = &world.archetypes[target_archetype_id];
+        for component in target_archetype.components.iter_details(components) {
+            if component.tag {
+                continue;
+            }
+            let storage = storage_mut_dyn(self.res, component).expect("storage");
+            let pushed = storage.flush_push(clone, target_archetype_id);
+            debug_assert_eq!(Some(index), pushed, "unexpected index (flush push)");
+        }
+        let groups: Vec<ComponentId> = components
+            .components
+            .iter()
+            .filter(|component| component.group.is_some() && component.clone_fn.is_some())
+            .map(ComponentDetails::id)
+            .collect();
+        group::sync_groups(self.res, components, &groups, clone);
+        Some(clone)
+    }
+
     /// Spawns/creates an new empty [`Entity`] in this `World` and returns a handle
     /// for modifying it.
     #[must_use]
@@ -494,3 +1047,130 @@ impl WorldMut<'_> {
         EntityMut::new(self.res, &mut self.world, entity, location)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use pulz_schedule::resource::Resources;
+
+    use crate::{component::Component, WorldExt};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    struct A(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(sparse)]
+    struct C(usize);
+
+    #[test]
+    fn test_entity_get_mut() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let mut entity = world.spawn();
+        entity.insert(A(1)).insert(C(2));
+        let id = entity.id();
+        drop(entity);
+
+        let mut entity = world.entity_mut(id).unwrap();
+        let a = entity.get_mut::<A>().unwrap();
+        a.0 += 10;
+        let c = entity.get_mut::<C>().unwrap();
+        c.0 += 10;
+        assert_eq!(Some(A(11)), entity.get_mut::<A>().copied());
+        assert_eq!(Some(C(12)), entity.borrow::<C>().as_deref().copied());
+
+        entity.remove::<A>();
+        assert_eq!(None, entity.get_mut::<A>());
+    }
+
+    #[test]
+    fn test_get_many_components_mut() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let a = world.spawn().insert(A(1)).insert(C(1)).id();
+        let b = world.spawn().insert(A(2)).id();
+        let c = world.spawn().insert(C(3)).id();
+
+        let [x, y] = world.get_many_components_mut::<A, 2>([a, b]).unwrap();
+        std::mem::swap(x, y);
+        assert_eq!(
+            Some(A(2)),
+            world.entity(a).unwrap().borrow::<A>().as_deref().copied()
+        );
+        assert_eq!(
+            Some(A(1)),
+            world.entity(b).unwrap().borrow::<A>().as_deref().copied()
+        );
+
+        let [x, y] = world.get_many_components_mut::<C, 2>([a, c]).unwrap();
+        x.0 += y.0;
+        assert_eq!(
+            Some(C(4)),
+            world.entity(a).unwrap().borrow::<C>().as_deref().copied()
+        );
+
+        assert!(world.get_many_components_mut::<A, 2>([a, a]).is_none());
+        assert!(world.get_many_components_mut::<A, 2>([a, c]).is_none());
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Component)]
+    #[component(clone)]
+    struct Name(String);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Component)]
+    #[component(sparse, clone)]
+    struct Tag(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    struct Foreign(usize);
+
+    impl Component for Foreign {
+        type Storage = crate::storage::ArchetypeStorage<Self>;
+    }
+
+    #[test]
+    fn test_clone_entity() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        world.init_clonable::<Foreign>();
+        let a = world
+            .spawn()
+            .insert(Name("a".to_string()))
+            .insert(Tag(1))
+            .insert(Foreign(2))
+            .id();
+        let b = world
+            .spawn()
+            .insert(Name("b".to_string()))
+            .insert(A(3))
+            .id();
+
+        let a2 = world.clone_entity(a).unwrap();
+        let e = world.entity(a2).unwrap();
+        assert_eq!(e.archetype().id, world.entity(a).unwrap().archetype().id);
+        assert_eq!(
+            Some(Name("a".to_string())),
+            e.borrow::<Name>().as_deref().cloned()
+        );
+        assert_eq!(Some(Tag(1)), e.borrow::<Tag>().as_deref().cloned());
+        assert_eq!(Some(Foreign(2)), e.borrow::<Foreign>().as_deref().copied());
+
+        // `A` is not clonable and is skipped
+        let mut skipped = Vec::new();
+        let b2 = world.clone_entity_with_skipped(b, &mut skipped).unwrap();
+        assert_eq!(vec![world.init::<A>().untyped()], skipped);
+        let e = world.entity(b2).unwrap();
+        assert_eq!(
+            Some(Name("b".to_string())),
+            e.borrow::<Name>().as_deref().cloned()
+        );
+        assert!(e.borrow::<A>().is_none());
+        assert!(e.borrow::<Tag>().is_none());
+        assert_eq!(
+            Some(A(3)),
+            world.entity(b).unwrap().borrow::<A>().as_deref().copied()
+        );
+
+        world.despawn(a2);
+        assert!(world.clone_entity(a2).is_none());
+    }
+}
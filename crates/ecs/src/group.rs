@@ -0,0 +1,426 @@
+use std::{any::TypeId, sync::Arc};
+
+use pulz_schedule::{
+    resource::{ResMut, ResourceAccess, ResourceId, Resources},
+    system::data::{SystemData, SystemDataFetch, SystemDataState},
+};
+
+use crate::{
+    component::{Component, ComponentId, Components, SyncGroupFn},
+    get_or_init_component,
+    storage::SparseSetStorage,
+    world::WorldMut,
+    Entity,
+};
+
+/// A tuple of components in a [`SparseSetStorage`], that can be owned by a
+/// group (see [`WorldMut::create_group`]).
+pub trait GroupComponents: Sized + 'static {
+    #[doc(hidden)]
+    type Ids: Copy + Send + Sync + 'static;
+    #[doc(hidden)]
+    type Fetch<'r>;
+    #[doc(hidden)]
+    type Storages<'a>;
+
+    /// Shared slices of the components of the group.
+    type Slices<'a>;
+
+    /// Exclusive slices of the components of the group.
+    type SlicesMut<'a>;
+
+    #[doc(hidden)]
+    fn init_components(res: &mut Resources, components: &mut Components) -> Vec<ComponentId>;
+    #[doc(hidden)]
+    fn init_storages(res: &mut Resources) -> Self::Ids;
+    #[doc(hidden)]
+    fn update_access(ids: &Self::Ids, access: &mut ResourceAccess);
+    #[doc(hidden)]
+    fn owner(res: &mut Resources, ids: &Self::Ids) -> Option<TypeId>;
+    #[doc(hidden)]
+    fn set_owner(res: &mut Resources, ids: &Self::Ids);
+    #[doc(hidden)]
+    fn first_entities(res: &mut Resources, ids: &Self::Ids) -> Vec<Entity>;
+    #[doc(hidden)]
+    fn sync(res: &mut Resources, ids: &Self::Ids, entity: Entity);
+    #[doc(hidden)]
+    fn fetch<'r>(res: &'r Resources, ids: &Self::Ids) -> Self::Fetch<'r>;
+    #[doc(hidden)]
+    fn storages<'a>(fetch: &'a mut Self::Fetch<'_>) -> Self::Storages<'a>;
+    #[doc(hidden)]
+    fn entities<'a>(storages: &'a Self::Storages<'_>) -> &'a [Entity];
+    #[doc(hidden)]
+    fn slices<'a>(storages: &'a Self::Storages<'_>) -> Self::Slices<'a>;
+    #[doc(hidden)]
+    fn slices_mut<'a>(storages: &'a mut Self::Storages<'_>) -> Self::SlicesMut<'a>;
+}
+
+impl WorldMut<'_> {
+    /// Creates a group, that owns the storages of the components `G`.
+    ///
+    /// The components of all entities having every component of `G` are
+    /// kept at the front of the dense arrays of the storages, in the same
+    /// order. This is maintained when components are inserted or removed, so
+    /// the components of the group can be iterated linearly with [`Group`].
+    ///
+    /// Does nothing, when the group was already created.
+    ///
+    /// # Panics
+    /// Panics when one of the components is already owned by an other group.
+    pub fn create_group<G: GroupComponents>(&mut self) {
+        let component_ids = G::init_components(self.res, &mut self.world.components);
+        let ids = G::init_storages(self.res);
+        if G::owner(self.res, &ids) == Some(TypeId::of::<G>()) {
+            return;
+        }
+        let components = &mut self.world.components;
+        for &id in &component_ids {
+            let component = components.get(id).expect("component");
+            assert!(
+                component.group.is_none(),
+                "component {} is already owned by a group",
+                component.name()
+            );
+        }
+        G::set_owner(self.res, &ids);
+        let sync: Arc<SyncGroupFn> = Arc::new(move |res, entity| G::sync(res, &ids, entity));
+        for &id in &component_ids {
+            components.components[id.offset()].group = Some(sync.clone());
+        }
+        for entity in G::first_entities(self.res, &ids) {
+            sync(self.res, entity);
+        }
+    }
+}
+
+/// Updates the groups of the given components for an entity, after
+/// components were inserted or removed.
+pub(crate) fn sync_groups(
+    res: &mut Resources,
+    components: &Components,
+    component_ids: &[ComponentId],
+    entity: Entity,
+) {
+    for id in component_ids {
+        if let Some(sync) = &components.components[id.offset()].group {
+            sync(res, entity);
+        }
+    }
+}
+
+/// Iterates the components of a group (see [`WorldMut::create_group`]) as
+/// parallel slices.
+///
+/// The group must be created, before the system is run.
+pub struct Group<'a, G: GroupComponents>(G::Storages<'a>);
+
+impl<G: GroupComponents> Group<'_, G> {
+    /// Returns the number of entities in the group.
+    #[inline]
+    pub fn len(&self) -> usize {
+        G::entities(&self.0).len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the entities of the group, in the same order as the slices.
+    #[inline]
+    pub fn entities(&self) -> &[Entity] {
+        G::entities(&self.0)
+    }
+
+    /// Returns the components of the group as parallel slices.
+    #[inline]
+    pub fn slices(&self) -> G::Slices<'_> {
+        G::slices(&self.0)
+    }
+
+    /// Returns the components of the group as parallel exclusive slices.
+    #[inline]
+    pub fn slices_mut(&mut self) -> G::SlicesMut<'_> {
+        G::slices_mut(&mut self.0)
+    }
+}
+
+#[doc(hidden)]
+pub struct GroupState<G: GroupComponents>(G::Ids);
+
+#[doc(hidden)]
+pub struct GroupFetch<'r, G: GroupComponents>(G::Fetch<'r>);
+
+impl<G: GroupComponents> SystemData for Group<'_, G> {
+    type State = GroupState<G>;
+    type Fetch<'r> = GroupFetch<'r, G>;
+    type Item<'a> = Group<'a, G>;
+
+    #[inline]
+    fn get<'a>(fetch: &'a mut Self::Fetch<'_>) -> Self::Item<'a> {
+        Group(G::storages(&mut fetch.0))
+    }
+}
+
+// SAFETY: storages are marked as accessed
+unsafe impl<G: GroupComponents> SystemDataState for GroupState<G> {
+    #[inline]
+    fn init(resources: &mut Resources) -> Self {
+        let ids = G::init_storages(resources);
+        assert_eq!(
+            Some(TypeId::of::<G>()),
+            G::owner(resources, &ids),
+            "group {} was not created",
+            std::any::type_name::<G>()
+        );
+        Self(ids)
+    }
+
+    fn update_access(&self, _resources: &Resources, access: &mut ResourceAccess) {
+        G::update_access(&self.0, access);
+    }
+}
+
+impl<'r, G: GroupComponents> SystemDataFetch<'r> for GroupFetch<'r, G> {
+    type State = GroupState<G>;
+
+    #[inline]
+    fn fetch(res: &'r Resources, state: &'r mut Self::State) -> Self {
+        Self(G::fetch(res, &state.0))
+    }
+}
+
+fn storage_mut<T>(
+    res: &mut Resources,
+    id: ResourceId<SparseSetStorage<T>>,
+) -> &mut SparseSetStorage<T>
+where
+    T: Component<Storage = SparseSetStorage<T>>,
+{
+    res.get_mut_id(id).expect("storage")
+}
+
+macro_rules! impl_group_components {
+    ([]) => ();
+    ([$(($name:ident,$index:tt)),+]) => (
+        impl<$($name),+> GroupComponents for ($($name,)+)
+        where
+            $($name: Component<Storage = SparseSetStorage<$name>>,)+
+        {
+            type Ids = ($(ResourceId<SparseSetStorage<$name>>,)+);
+            type Fetch<'r> = ($(ResMut<'r, SparseSetStorage<$name>>,)+);
+            type Storages<'a> = ($(&'a mut SparseSetStorage<$name>,)+);
+            type Slices<'a> = ($(&'a [$name],)+);
+            type SlicesMut<'a> = ($(&'a mut [$name],)+);
+
+            fn init_components(res: &mut Resources, components: &mut Components) -> Vec<ComponentId> {
+                vec![$(get_or_init_component::<$name>(res, components).1.untyped()),+]
+            }
+
+            #[inline]
+            fn init_storages(res: &mut Resources) -> Self::Ids {
+                ($(res.init::<SparseSetStorage<$name>>(),)+)
+            }
+
+            fn update_access(ids: &Self::Ids, access: &mut ResourceAccess) {
+                $(access.add_exclusive_checked(ids.$index);)+
+            }
+
+            fn owner(res: &mut Resources, ids: &Self::Ids) -> Option<TypeId> {
+                storage_mut(res, ids.0).group()
+            }
+
+            fn set_owner(res: &mut Resources, ids: &Self::Ids) {
+                $(storage_mut(res, ids.$index).set_group(TypeId::of::<Self>());)+
+            }
+
+            fn first_entities(res: &mut Resources, ids: &Self::Ids) -> Vec<Entity> {
+                storage_mut(res, ids.0).entities().to_vec()
+            }
+
+            fn sync(res: &mut Resources, ids: &Self::Ids, entity: Entity) {
+                let complete = $(storage_mut(res, ids.$index).contains_key(entity))&&+;
+                $(
+                    let storage = storage_mut(res, ids.$index);
+                    if complete {
+                        storage.enter_group(entity);
+                    } else {
+                        storage.leave_group(entity);
+                    }
+                )+
+            }
+
+            #[inline]
+            fn fetch<'r>(res: &'r Resources, ids: &Self::Ids) -> Self::Fetch<'r> {
+                ($(res.borrow_res_mut_id(ids.$index).expect("storage"),)+)
+            }
+
+            #[inline]
+            fn storages<'a>(fetch: &'a mut Self::Fetch<'_>) -> Self::Storages<'a> {
+                ($(&mut *fetch.$index,)+)
+            }
+
+            #[inline]
+            fn entities<'a>(storages: &'a Self::Storages<'_>) -> &'a [Entity] {
+                &storages.0.entities()[..storages.0.group_len()]
+            }
+
+            #[inline]
+            fn slices<'a>(storages: &'a Self::Storages<'_>) -> Self::Slices<'a> {
+                ($(&storages.$index.values()[..storages.$index.group_len()],)+)
+            }
+
+            #[inline]
+            fn slices_mut<'a>(storages: &'a mut Self::Storages<'_>) -> Self::SlicesMut<'a> {
+                ($({
+                    let len = storages.$index.group_len();
+                    &mut storages.$index.values_mut()[..len]
+                },)+)
+            }
+        }
+    )
+}
+
+pulz_functional_utils::generate_variadic_array! {[T,#] impl_group_components!{}}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use pulz_schedule::{resource::Resources, schedule::Schedule};
+
+    use super::Group;
+    use crate::{component::Component, entity::Entity, storage::SparseSetStorage, WorldExt};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(storage = SparseSetStorage, clone)]
+    struct Pos(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(storage = SparseSetStorage, clone)]
+    struct Vel(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(storage = SparseSetStorage, clone)]
+    struct Mass(usize);
+
+    /// Checks that the group contains exactly the entities with all
+    /// components, in the same order in all storages.
+    fn assert_group(resources: &Resources) -> Vec<Entity> {
+        let pos = resources.borrow_res::<SparseSetStorage<Pos>>().unwrap();
+        let vel = resources.borrow_res::<SparseSetStorage<Vel>>().unwrap();
+        let mass = resources.borrow_res::<SparseSetStorage<Mass>>().unwrap();
+        let len = pos.group_len();
+        assert_eq!(len, vel.group_len());
+        assert_eq!(len, mass.group_len());
+        assert_eq!(pos.entities()[..len], vel.entities()[..len]);
+        assert_eq!(pos.entities()[..len], mass.entities()[..len]);
+        for (e, p) in pos.iter() {
+            let complete = vel.contains_key(e) && mass.contains_key(e);
+            assert_eq!(complete, pos.entities()[..len].contains(&e));
+            if complete {
+                assert_eq!(Some(p.0), vel.get(e).map(|v| v.0));
+            }
+        }
+        let mut entities = pos.entities()[..len].to_vec();
+        entities.sort_unstable();
+        entities
+    }
+
+    #[test]
+    fn test_group() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let entities: Vec<_> = (0..30)
+            .map(|i| {
+                let mut e = world.spawn();
+                e.insert(Pos(i));
+                if i % 2 == 0 {
+                    e.insert(Vel(i));
+                }
+                if i % 3 == 0 {
+                    e.insert(Mass(i));
+                }
+                e.id()
+            })
+            .collect();
+        world.create_group::<(Pos, Vel, Mass)>();
+        world.create_group::<(Pos, Vel, Mass)>();
+        drop(world);
+        let expected: Vec<_> = entities.iter().copied().step_by(6).collect();
+        assert_eq!(expected, assert_group(&resources));
+
+        let mut world = resources.world_mut();
+        world
+            .entity_mut(entities[1])
+            .unwrap()
+            .insert(Vel(1))
+            .insert(Mass(1));
+        world.entity_mut(entities[6]).unwrap().remove::<Vel>();
+        world.entity_mut(entities[12]).unwrap().insert(Pos(12));
+        world.despawn(entities[0]);
+        world.insert_for_each([entities[2], entities[4]], |_| Mass(0));
+        world.remove_for_each::<Mass>([entities[18], entities[24], entities[29]]);
+        let clone = world.clone_entity(entities[12]).unwrap();
+        let spawned = world
+            .spawn()
+            .insert(Pos(30))
+            .insert(Vel(30))
+            .insert(Mass(30))
+            .id();
+        drop(world);
+
+        let mut expected = vec![
+            entities[1],
+            entities[2],
+            entities[4],
+            entities[12],
+            clone,
+            spawned,
+        ];
+        expected.sort_unstable();
+        assert_eq!(expected, assert_group(&resources));
+    }
+
+    #[test]
+    fn test_group_system() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        world.create_group::<(Pos, Vel)>();
+        for i in 0..10 {
+            let mut e = world.spawn();
+            e.insert(Pos(i));
+            if i < 5 {
+                e.insert(Vel(1));
+            }
+        }
+        drop(world);
+
+        let lens: Arc<Mutex<Vec<usize>>> = Default::default();
+        let lens2 = lens.clone();
+        let mut schedule = Schedule::new();
+        schedule.add_system(move |mut group: Group<'_, (Pos, Vel)>| {
+            lens2.lock().unwrap().push(group.len());
+            let (pos, vel) = group.slices_mut();
+            for (p, v) in pos.iter_mut().zip(vel.iter()) {
+                p.0 += v.0 * 100;
+            }
+        });
+        schedule.run(&mut resources);
+
+        assert_eq!(vec![5], *lens.lock().unwrap());
+        let pos = resources.borrow_res::<SparseSetStorage<Pos>>().unwrap();
+        let mut values: Vec<_> = pos.values().iter().map(|p| p.0).collect();
+        values.sort_unstable();
+        assert_eq!(vec![5, 6, 7, 8, 9, 100, 101, 102, 103, 104], values);
+    }
+
+    #[test]
+    #[should_panic(expected = "already owned by a group")]
+    fn test_group_overlap() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        world.create_group::<(Pos, Vel)>();
+        world.create_group::<(Vel, Mass)>();
+    }
+}
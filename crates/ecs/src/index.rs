@@ -0,0 +1,514 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hash},
+};
+
+use pulz_schedule::{
+    prelude::*,
+    resource::ResourceAccess,
+    system::data::{SystemData, SystemDataFetch, SystemDataState},
+};
+use slotmap::SparseSecondaryMap;
+
+use crate::{
+    archetype::{Archetype, ArchetypeId},
+    component::{Component, ComponentDetails},
+    insert_sorted,
+    storage::Storage,
+    Entity,
+};
+
+/// A sparse storage with a secondary index from the component values to the
+/// entities.
+///
+/// Entities are looked up by value with [`get`](Self::get) and
+/// [`get_all`](Self::get_all), or with a [`Lookup`] in systems. The index is
+/// maintained when components are inserted, replaced or removed. Components
+/// in this storage are immutable (see [`Immutable`](crate::storage::Immutable)),
+/// so the index can't get out of sync.
+///
+/// Multiple entities can have the same value. Use [`UniqueIndexedStorage`]
+/// to reject duplicates.
+pub struct IndexedStorage<T> {
+    values: SparseSecondaryMap<Entity, T>,
+    /// the entities by the hash of their value, ordered by their id
+    index: HashMap<u64, Vec<Entity>>,
+    hasher: RandomState,
+}
+
+impl<T> Default for IndexedStorage<T> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            values: SparseSecondaryMap::new(),
+            index: HashMap::new(),
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<T: Hash + Eq> IndexedStorage<T> {
+    /// Returns the entity with the given value, or the one with the lowest
+    /// id, when multiple entities have this value.
+    #[inline]
+    pub fn get(&self, value: &T) -> Option<Entity> {
+        self.get_all(value).next()
+    }
+
+    /// Returns all entities with the given value, ordered by their id.
+    pub fn get_all<'a>(&'a self, value: &'a T) -> impl Iterator<Item = Entity> + 'a {
+        self.index
+            .get(&self.hasher.hash_one(value))
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(move |&entity| self.values.get(entity) == Some(value))
+    }
+
+    /// Returns `true`, when an entity has the given value.
+    #[inline]
+    pub fn contains_value(&self, value: &T) -> bool {
+        self.get(value).is_some()
+    }
+
+    fn link(&mut self, entity: Entity, hash: u64) {
+        insert_sorted(self.index.entry(hash).or_default(), entity);
+    }
+
+    fn unlink(&mut self, entity: Entity, hash: u64) {
+        if let Some(entities) = self.index.get_mut(&hash) {
+            if let Ok(pos) = entities.binary_search(&entity) {
+                entities.remove(pos);
+            }
+            if entities.is_empty() {
+                self.index.remove(&hash);
+            }
+        }
+    }
+
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        let value = self.values.remove(entity)?;
+        self.unlink(entity, self.hasher.hash_one(&value));
+        Some(value)
+    }
+}
+
+impl<T> Storage for IndexedStorage<T>
+where
+    T: Hash + Eq + Send + Sync + 'static,
+{
+    const SPARSE: bool = true;
+    const MUTABLE: bool = false;
+    type Component = T;
+
+    #[inline]
+    fn fast_contains(
+        res: &Resources,
+        entity: Entity,
+        component: &ComponentDetails,
+        _archetype: &Archetype,
+    ) -> bool {
+        res.borrow_res_id(component.storage_id.typed::<Self>())
+            .map_or(false, |s| s.values.contains_key(entity))
+    }
+
+    #[inline]
+    fn contains(&self, entity: Entity, _archetype: ArchetypeId, _index: usize) -> bool {
+        self.values.contains_key(entity)
+    }
+
+    #[inline]
+    fn swap_remove(&mut self, entity: Entity, _archetype: ArchetypeId, _index: usize) -> Option<T> {
+        self.remove(entity)
+    }
+
+    fn insert(&mut self, entity: Entity, value: T) {
+        let hash = self.hasher.hash_one(&value);
+        if let Some(old) = self.values.insert(entity, value) {
+            self.unlink(entity, self.hasher.hash_one(&old));
+        }
+        self.link(entity, hash);
+    }
+
+    #[inline]
+    fn flush_replace(&mut self, _entity: Entity, _archetype: ArchetypeId, _index: usize) -> bool {
+        true
+    }
+
+    #[inline]
+    fn flush_push(&mut self, _entity: Entity, _archetype: ArchetypeId) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn swap_remove_and_insert(
+        &mut self,
+        _remove_from_archetype: ArchetypeId,
+        _remove_from_index: usize,
+        _insert_to_archetype: ArchetypeId,
+    ) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn get(&self, entity: Entity, _archetype: ArchetypeId, _index: usize) -> Option<&T> {
+        self.values.get(entity)
+    }
+
+    /// Indexed components can't be borrowed mutably, because this would bypass
+    /// the index.
+    #[inline]
+    fn get_mut(
+        &mut self,
+        _entity: Entity,
+        _archetype: ArchetypeId,
+        _index: usize,
+    ) -> Option<&mut T> {
+        None
+    }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        entities.extend(self.values.keys());
+    }
+}
+
+/// A storage, that maintains an [`IndexedStorage`].
+///
+/// Implemented by [`IndexedStorage`], [`UniqueIndexedStorage`], and by
+/// [`Tracked`](crate::storage::Tracked) and
+/// [`Immutable`](crate::storage::Immutable) wrapping one of them.
+pub trait Indexed: Storage {
+    /// Returns the index of the stored components.
+    fn index(&self) -> &IndexedStorage<Self::Component>;
+}
+
+impl<T> Indexed for IndexedStorage<T>
+where
+    T: Hash + Eq + Send + Sync + 'static,
+{
+    #[inline]
+    fn index(&self) -> &Self {
+        self
+    }
+}
+
+/// An [`IndexedStorage`], where every value can only be used by a single
+/// entity (`#[component(storage = UniqueIndexedStorage)]`).
+///
+/// # Panics
+/// Inserting a value, that is already used by another entity, panics.
+pub struct UniqueIndexedStorage<T>(IndexedStorage<T>);
+
+impl<T> Default for UniqueIndexedStorage<T> {
+    #[inline]
+    fn default() -> Self {
+        Self(IndexedStorage::default())
+    }
+}
+
+impl<T: Hash + Eq> UniqueIndexedStorage<T> {
+    /// Returns the entity with the given value.
+    #[inline]
+    pub fn get(&self, value: &T) -> Option<Entity> {
+        self.0.get(value)
+    }
+
+    /// Returns `true`, when an entity has the given value.
+    #[inline]
+    pub fn contains_value(&self, value: &T) -> bool {
+        self.0.contains_value(value)
+    }
+}
+
+impl<T> Storage for UniqueIndexedStorage<T>
+where
+    T: Hash + Eq + Send + Sync + 'static,
+{
+    const SPARSE: bool = true;
+    const MUTABLE: bool = false;
+    type Component = T;
+
+    #[inline]
+    fn fast_contains(
+        res: &Resources,
+        entity: Entity,
+        component: &ComponentDetails,
+        _archetype: &Archetype,
+    ) -> bool {
+        res.borrow_res_id(component.storage_id.typed::<Self>())
+            .map_or(false, |s| s.0.values.contains_key(entity))
+    }
+
+    #[inline]
+    fn contains(&self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        self.0.contains(entity, archetype, index)
+    }
+
+    #[inline]
+    fn swap_remove(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> Option<T> {
+        self.0.swap_remove(entity, archetype, index)
+    }
+
+    fn insert(&mut self, entity: Entity, value: T) {
+        if let Some(other) = self.0.get(&value) {
+            assert_eq!(
+                entity, other,
+                "value of unique indexed component is already used by another entity"
+            );
+        }
+        self.0.insert(entity, value);
+    }
+
+    #[inline]
+    fn flush_replace(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        self.0.flush_replace(entity, archetype, index)
+    }
+
+    #[inline]
+    fn flush_push(&mut self, entity: Entity, archetype: ArchetypeId) -> Option<usize> {
+        self.0.flush_push(entity, archetype)
+    }
+
+    #[inline]
+    fn swap_remove_and_insert(
+        &mut self,
+        remove_from_archetype: ArchetypeId,
+        remove_from_index: usize,
+        insert_to_archetype: ArchetypeId,
+    ) -> Option<usize> {
+        self.0.swap_remove_and_insert(
+            remove_from_archetype,
+            remove_from_index,
+            insert_to_archetype,
+        )
+    }
+
+    #[inline]
+    fn get(&self, entity: Entity, archetype: ArchetypeId, index: usize) -> Option<&T> {
+        Storage::get(&self.0, entity, archetype, index)
+    }
+
+    #[inline]
+    fn get_mut(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> Option<&mut T> {
+        self.0.get_mut(entity, archetype, index)
+    }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        self.0.sparse_len()
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        self.0.collect_sparse_entities(entities)
+    }
+}
+
+impl<T> Indexed for UniqueIndexedStorage<T>
+where
+    T: Hash + Eq + Send + Sync + 'static,
+{
+    #[inline]
+    fn index(&self) -> &IndexedStorage<T> {
+        &self.0
+    }
+}
+
+/// Looks up entities by the value of an indexed component (see
+/// [`IndexedStorage`] and [`Indexed`]).
+pub struct Lookup<'a, C>(&'a IndexedStorage<C>);
+
+impl<C: Hash + Eq> Lookup<'_, C> {
+    /// Returns the entity with the given value, or the one with the lowest
+    /// id, when multiple entities have this value.
+    #[inline]
+    pub fn get(&self, value: &C) -> Option<Entity> {
+        self.0.get(value)
+    }
+
+    /// Returns all entities with the given value, ordered by their id.
+    #[inline]
+    pub fn get_all<'a>(&'a self, value: &'a C) -> impl Iterator<Item = Entity> + 'a {
+        self.0.get_all(value)
+    }
+}
+
+#[doc(hidden)]
+pub struct LookupState<C: Component>(ResourceId<C::Storage>);
+
+#[doc(hidden)]
+pub struct LookupFetch<'a, C: Component>(Res<'a, C::Storage>);
+
+impl<C> SystemData for Lookup<'_, C>
+where
+    C: Component + Hash + Eq,
+    C::Storage: Indexed,
+{
+    type State = LookupState<C>;
+    type Fetch<'r> = LookupFetch<'r, C>;
+    type Item<'a> = Lookup<'a, C>;
+
+    #[inline]
+    fn get<'a>(fetch: &'a mut Self::Fetch<'_>) -> Self::Item<'a> {
+        Lookup(fetch.0.index())
+    }
+}
+
+// SAFETY: storage is marked as accessed
+unsafe impl<C> SystemDataState for LookupState<C>
+where
+    C: Component + Hash + Eq,
+    C::Storage: Indexed,
+{
+    #[inline]
+    fn init(resources: &mut Resources) -> Self {
+        Self(resources.init::<C::Storage>())
+    }
+
+    fn update_access(&self, _resources: &Resources, access: &mut ResourceAccess) {
+        access.add_shared_checked(self.0);
+    }
+}
+
+impl<'r, C> SystemDataFetch<'r> for LookupFetch<'r, C>
+where
+    C: Component + Hash + Eq,
+    C::Storage: Indexed,
+{
+    type State = LookupState<C>;
+    #[inline]
+    fn fetch(res: &'r Resources, state: &'r mut Self::State) -> Self {
+        Self(res.borrow_res_id(state.0).expect("storage"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use pulz_schedule::{resource::Resources, schedule::Schedule};
+
+    use super::{IndexedStorage, Lookup, UniqueIndexedStorage};
+    use crate::{component::Component, entity::Entity, storage::Tracked, WorldExt};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Component)]
+    #[component(storage = IndexedStorage)]
+    struct NetId(u32);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Component)]
+    #[component(storage = IndexedStorage)]
+    enum Team {
+        Red,
+        Blue,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Component)]
+    #[component(tracked, storage = UniqueIndexedStorage)]
+    struct Name(&'static str);
+
+    #[test]
+    fn test_indexed_storage() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let a = world.spawn().insert(NetId(1)).insert(Team::Red).id();
+        let b = world.spawn().insert(NetId(2)).insert(Team::Red).id();
+        let c = world.spawn().insert(NetId(3)).insert(Team::Blue).id();
+        drop(world);
+
+        {
+            let ids = resources.borrow_res::<IndexedStorage<NetId>>().unwrap();
+            assert_eq!(Some(b), ids.get(&NetId(2)));
+            assert_eq!(None, ids.get(&NetId(4)));
+            let teams = resources.borrow_res::<IndexedStorage<Team>>().unwrap();
+            assert_eq!(vec![a, b], teams.get_all(&Team::Red).collect::<Vec<_>>());
+        }
+
+        // replace and remove
+        let mut world = resources.world_mut();
+        world
+            .entity_mut(a)
+            .unwrap()
+            .insert(NetId(4))
+            .insert(Team::Blue);
+        world.entity_mut(b).unwrap().remove::<NetId>();
+        world.despawn(c);
+        drop(world);
+
+        let ids = resources.borrow_res::<IndexedStorage<NetId>>().unwrap();
+        assert_eq!(None, ids.get(&NetId(1)));
+        assert_eq!(None, ids.get(&NetId(2)));
+        assert_eq!(None, ids.get(&NetId(3)));
+        assert_eq!(Some(a), ids.get(&NetId(4)));
+        let teams = resources.borrow_res::<IndexedStorage<Team>>().unwrap();
+        assert_eq!(vec![b], teams.get_all(&Team::Red).collect::<Vec<_>>());
+        assert_eq!(vec![a], teams.get_all(&Team::Blue).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut resources = Resources::new();
+        let mut schedule = Schedule::new();
+        type Found = (Option<Entity>, Vec<Entity>);
+        let found: Arc<Mutex<Vec<Found>>> = Default::default();
+        let found2 = found.clone();
+        schedule.add_system(move |ids: Lookup<'_, NetId>, teams: Lookup<'_, Team>| {
+            found2
+                .lock()
+                .unwrap()
+                .push((ids.get(&NetId(7)), teams.get_all(&Team::Blue).collect()));
+        });
+        schedule.run(&mut resources);
+
+        let mut world = resources.world_mut();
+        let a = world.spawn().insert(NetId(7)).insert(Team::Blue).id();
+        let b = world.spawn().insert(Team::Blue).id();
+        drop(world);
+        schedule.run(&mut resources);
+
+        assert_eq!(
+            vec![(None, vec![]), (Some(a), vec![a, b])],
+            *found.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unique_tracked_lookup() {
+        let mut resources = Resources::new();
+        let mut schedule = Schedule::new();
+        let found: Arc<Mutex<Vec<Option<Entity>>>> = Default::default();
+        let found2 = found.clone();
+        schedule.add_system(move |names: Lookup<'_, Name>| {
+            found2.lock().unwrap().push(names.get(&Name("a")));
+        });
+
+        let mut world = resources.world_mut();
+        let a = world.spawn().insert(Name("a")).id();
+        // replacing with the same value is not a duplicate
+        world.entity_mut(a).unwrap().insert(Name("a"));
+        let b = world.spawn().insert(Name("b")).id();
+        world.despawn(b);
+        drop(world);
+        schedule.run(&mut resources);
+
+        assert_eq!(vec![Some(a)], *found.lock().unwrap());
+        let names = resources
+            .borrow_res::<Tracked<UniqueIndexedStorage<Name>>>()
+            .unwrap();
+        assert_eq!(vec![b], names.removed);
+    }
+
+    #[test]
+    #[should_panic(expected = "already used by another entity")]
+    fn test_unique_duplicate() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        world.spawn().insert(Name("a"));
+        world.spawn().insert(Name("a"));
+    }
+}
@@ -32,12 +32,20 @@ pub use pulz_schedule::*;
 pub enum Void {}
 
 pub mod archetype;
+mod batch;
 pub mod component;
 pub mod query;
 
 pub mod entity;
 mod entity_ref;
+pub mod group;
+pub mod index;
+pub mod prefab;
+mod ref_counted;
+pub mod relation;
 pub mod removed;
+pub mod soa;
+mod sparse_set;
 pub mod storage;
 pub mod world;
 
@@ -109,6 +117,7 @@ where
             let schedule = res.get_mut::<Schedule>().unwrap();
             <T::Storage as Storage>::install_systems(schedule);
         }
+        comps.resolve_required(res, component_id.untyped());
 
         (storage_id, component_id)
     }
@@ -0,0 +1,548 @@
+//! Prefabs: template entities, whose components are inherited by their
+//! instances.
+//!
+//! Instances don't get their own copies of the components of their prefab.
+//! They only see them through [`Inherit`] and
+//! [`borrow_inherited`](crate::entity::EntityRef::borrow_inherited), so
+//! `contains`, `borrow` and queries for `&T` only see the components of the
+//! instance itself. [`EntityMut::get_mut`] is copy-on-write for clonable
+//! components: it inserts a copy of the inherited component first. Queries
+//! for `&mut T` can't insert components, and only visit the instances with
+//! their own copy ([`EntityMut::override_inherited`] creates it explicitly).
+//!
+//! Changes of a prefab are visible to the instances without an override.
+//! Instances spawned with [`WorldMut::instantiate_copy`] opt out of this:
+//! they get copies of all clonable components of their prefab.
+
+use std::{borrow::Cow, cell::Cell, marker::PhantomData};
+
+use pulz_schedule::resource::{ResourceAccess, ResourceId};
+
+use crate::{
+    archetype::Archetype,
+    component::{Component, ComponentDetails, ComponentId, Components},
+    entity::{Entity, EntityMut},
+    query::{QueryParam, QueryParamFetch, QueryParamState, ReadOnlyQueryParam},
+    relation::{Relation, RelationStorage},
+    resource::{Res, Resources, ResourcesSend},
+    storage::{get_component, ArchetypeStorage, Storage},
+    world::WorldMut,
+    WorldInner,
+};
+
+/// Marks an entity as a template for other entities.
+///
+/// Instances of a prefab are spawned with [`WorldMut::instantiate`]. They
+/// are related to their prefab with an [`IsA`] relation, and inherit all
+/// components of the prefab, that they don't have themselves.
+///
+/// Prefabs are normal entities, and are matched by queries. Use
+/// `Without<&Prefab, Q>` to exclude them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Prefab;
+
+impl Component for Prefab {
+    type Storage = ArchetypeStorage<Self>;
+}
+
+/// The relation kind between an instance and its prefab
+/// (`Relation<IsA>`).
+///
+/// Inherited components are looked up along the `IsA` chain, so a prefab can
+/// itself be an instance of an other prefab. When an entity has multiple
+/// prefabs, only the first one (by id) is used.
+pub struct IsA;
+
+/// The maximum length of an `IsA` chain, that is followed when looking up
+/// inherited components (protects against cycles).
+pub(crate) const MAX_DEPTH: usize = 32;
+
+/// Looks up the component of the nearest prefab of `entity`, that has the
+/// component (without the component of `entity` itself).
+pub(crate) fn inherited<'s, S: Storage>(
+    storage: &'s S,
+    component: &ComponentDetails,
+    relations: &RelationStorage<IsA>,
+    world: &WorldInner,
+    entity: Entity,
+) -> Option<&'s S::Component> {
+    let prefab = inherited_from(storage, component, relations, world, entity)?;
+    get_prefab_component(storage, component, world, prefab)
+}
+
+/// Returns the nearest prefab of `entity`, that has the component.
+fn inherited_from<S: Storage>(
+    storage: &S,
+    component: &ComponentDetails,
+    relations: &RelationStorage<IsA>,
+    world: &WorldInner,
+    entity: Entity,
+) -> Option<Entity> {
+    let mut current = entity;
+    for _ in 0..MAX_DEPTH {
+        let &prefab = relations.targets(current).first()?;
+        if get_prefab_component(storage, component, world, prefab).is_some() {
+            return Some(prefab);
+        }
+        current = prefab;
+    }
+    None
+}
+
+#[inline]
+fn get_prefab_component<'s, S: Storage>(
+    storage: &'s S,
+    component: &ComponentDetails,
+    world: &WorldInner,
+    prefab: Entity,
+) -> Option<&'s S::Component> {
+    let location = world.entities.get(prefab)?;
+    let archetype = &world.archetypes[location.archetype_id];
+    get_component(storage, component, prefab, archetype, location.index)
+}
+
+/// Returns the prefab of `entity` (the first target of its `IsA` relation).
+pub(crate) fn prefab_of(
+    res: &Resources,
+    components: &Components,
+    entity: Entity,
+) -> Option<Entity> {
+    let relations = borrow_relations(res, components)?;
+    relations.targets(entity).first().copied()
+}
+
+/// Borrows the storage of the `IsA` relations, if it was initialized.
+pub(crate) fn borrow_relations<'a>(
+    res: &'a Resources,
+    components: &Components,
+) -> Option<Res<'a, RelationStorage<IsA>>> {
+    let component_id = components.id::<Relation<IsA>>()?;
+    let component = components.get(component_id)?;
+    res.borrow_res_id(component.storage_id.typed())
+}
+
+impl WorldMut<'_> {
+    /// Spawns a new [`Prefab`] entity.
+    pub fn spawn_prefab(&mut self) -> EntityMut<'_> {
+        let mut entity = self.spawn();
+        entity.insert(Prefab);
+        entity
+    }
+
+    /// Spawns an instance of `prefab`.
+    ///
+    /// The instance doesn't get copies of the components of the prefab, but
+    /// shares them, until it gets its own values (overrides). Changes of the
+    /// prefab are visible to all instances without an override. A copy of an
+    /// inherited component is created with [`EntityMut::override_inherited`],
+    /// or when a clonable component is borrowed with [`EntityMut::get_mut`]
+    /// (copy-on-write). Use [`instantiate_copy`](Self::instantiate_copy) to
+    /// opt out of the propagation.
+    ///
+    /// Inherited components are read with [`Inherit`] in queries, and with
+    /// [`EntityRef::borrow_inherited`](crate::entity::EntityRef::borrow_inherited).
+    ///
+    /// Returns `None`, when `prefab` doesn't exist.
+    pub fn instantiate(&mut self, prefab: Entity) -> Option<EntityMut<'_>> {
+        if !self.world.entities.contains(prefab) {
+            return None;
+        }
+        let mut entity = self.spawn();
+        entity.relate::<IsA>(prefab);
+        Some(entity)
+    }
+
+    /// Spawns an instance of `prefab`, that gets copies of all clonable
+    /// components of its prefab (see [`EntityMut::override_all_inherited`]),
+    /// so later changes of the prefab don't propagate to it.
+    ///
+    /// Components, that are not clonable, are still shared with the prefab.
+    ///
+    /// Returns `None`, when `prefab` doesn't exist.
+    pub fn instantiate_copy(&mut self, prefab: Entity) -> Option<EntityMut<'_>> {
+        let mut entity = self.instantiate(prefab)?;
+        entity.override_all_inherited();
+        Some(entity)
+    }
+}
+
+/// Query for a component of an entity, or the component inherited from its
+/// prefab (see [`WorldMut::instantiate`]).
+///
+/// Matches all entities that have the component, or that are an instance of
+/// a prefab with the component. The `Relation<IsA>` component must be
+/// initialized, before the query is created.
+///
+/// The world is borrowed to lookup the prefabs, so this query can't be used
+/// together with a [`WorldMut`].
+///
+/// Every archetype is visited, because instances can be in any archetype
+/// (the `IsA` relation is sparse). Prefabs are only looked up for entities
+/// without the component, and only once per entity.
+pub struct Inherit<T>(PhantomData<fn() -> T>);
+
+impl<T: Component> QueryParam for Inherit<T> {
+    type State = QryInheritState<T>;
+    type Fetch<'w> = QryInheritFetch<'w, T>;
+}
+
+// SAFETY: only shared access
+unsafe impl<T: Component> ReadOnlyQueryParam for Inherit<T> {}
+
+#[doc(hidden)]
+pub struct QryInheritState<T: Component> {
+    component_id: ComponentId<T>,
+    storage_id: ResourceId<T::Storage>,
+    relations_id: ResourceId<RelationStorage<IsA>>,
+    world_id: ResourceId<WorldInner>,
+    name: String,
+}
+
+unsafe impl<T: Component> QueryParamState for QryInheritState<T> {
+    #[inline]
+    fn init(res: &Resources, components: &Components) -> Self {
+        let component_id = components.expect_id::<T>();
+        let component = components.get(component_id).unwrap();
+        assert!(
+            <T::Storage as Storage>::BORROWABLE,
+            "component {} is not stored as a whole and can't be inherited",
+            component.name()
+        );
+        let relations_id = components.expect_id::<Relation<IsA>>();
+        Self {
+            component_id,
+            storage_id: component.storage_id.typed(),
+            relations_id: components.get(relations_id).unwrap().storage_id.typed(),
+            world_id: res.expect_id::<WorldInner>(),
+            name: component.name().to_owned(),
+        }
+    }
+
+    #[inline]
+    fn is_registered(components: &Components) -> bool {
+        components.id::<T>().is_some() && components.id::<Relation<IsA>>().is_some()
+    }
+
+    #[inline]
+    fn update_access(&self, access: &mut ResourceAccess) {
+        access.add_shared_checked(self.storage_id);
+        access.set_name(self.storage_id, self.name.clone());
+        access.add_shared_checked(self.relations_id);
+        access.add_shared(self.world_id);
+    }
+
+    #[inline]
+    fn matches_archetype(&self, _archetype: &Archetype) -> bool {
+        // `IsA` is sparse, so instances are not grouped by archetype
+        true
+    }
+
+    fn type_name(&self) -> Cow<'static, str> {
+        format!("Inherit<{}>", self.name).into()
+    }
+}
+
+#[doc(hidden)]
+pub struct QryInheritFetch<'w, T: Component> {
+    component_id: ComponentId<T>,
+    storage: Res<'w, T::Storage>,
+    relations: Res<'w, RelationStorage<IsA>>,
+    world: Res<'w, WorldInner>,
+    /// the current archetype contains the component
+    own: bool,
+    /// the prefab, that was found by `matches_entity` for the entity at an
+    /// index of the current archetype
+    found: Cell<Option<(usize, Entity)>>,
+}
+
+impl<T: Component> QryInheritFetch<'_, T> {
+    #[inline]
+    fn component(&self) -> &ComponentDetails {
+        self.world
+            .components
+            .get(self.component_id)
+            .expect("component")
+    }
+
+    #[inline]
+    fn get_own(&self, archetype: &Archetype, index: usize) -> Option<&T> {
+        let entity = archetype.entities[index];
+        get_component(&*self.storage, self.component(), entity, archetype, index)
+    }
+
+    #[inline]
+    fn prefab(&self, entity: Entity) -> Option<Entity> {
+        inherited_from(
+            &*self.storage,
+            self.component(),
+            &self.relations,
+            &self.world,
+            entity,
+        )
+    }
+}
+
+impl<'w, T: Component> QueryParamFetch<'w> for QryInheritFetch<'w, T> {
+    type State = QryInheritState<T>;
+    type Item<'a>
+        = &'a T
+    where
+        Self: 'a;
+
+    #[inline]
+    fn fetch(res: &'w ResourcesSend, state: &QryInheritState<T>) -> Self {
+        Self {
+            component_id: state.component_id,
+            storage: res
+                .borrow_res_id(state.storage_id)
+                .expect("unable to borrow component"),
+            relations: res
+                .borrow_res_id(state.relations_id)
+                .expect("unable to borrow relation"),
+            world: res
+                .borrow_res_id(state.world_id)
+                .expect("unable to borrow world"),
+            own: false,
+            found: Cell::new(None),
+        }
+    }
+
+    #[inline]
+    fn set_archetype(&mut self, _state: &Self::State, archetype: &Archetype) {
+        self.own =
+            !<T::Storage as Storage>::SPARSE && archetype.contains_component_id(self.component_id);
+        self.found.set(None);
+    }
+
+    #[inline]
+    fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_> {
+        if let Some(value) = self.get_own(archetype, index) {
+            return value;
+        }
+        let prefab = match self.found.take() {
+            Some((found_index, prefab)) if found_index == index => Some(prefab),
+            _ => self.prefab(archetype.entities[index]),
+        };
+        prefab
+            .and_then(|prefab| {
+                get_prefab_component(&*self.storage, self.component(), &self.world, prefab)
+            })
+            .expect("unable to get component item")
+    }
+
+    #[inline]
+    fn matches_entity(&self, archetype: &Archetype, index: usize) -> bool {
+        if self.own || self.get_own(archetype, index).is_some() {
+            return true;
+        }
+        let prefab = self.prefab(archetype.entities[index]);
+        self.found.set(prefab.map(|prefab| (index, prefab)));
+        prefab.is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pulz_schedule::resource::Resources;
+
+    use super::{Inherit, Prefab};
+    use crate::{
+        component::Component,
+        entity::Entity,
+        query::{Query, Without},
+        WorldExt,
+    };
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    struct Mesh(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(sparse)]
+    struct Health(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(clone)]
+    struct Scale(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(clone, sparse)]
+    struct Name(&'static str);
+
+    #[test]
+    fn test_instantiate() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let tree = world.spawn_prefab().insert(Mesh(1)).insert(Health(10)).id();
+        let a = world.instantiate(tree).unwrap().id();
+        let b = world.instantiate(tree).unwrap().insert(Mesh(2)).id();
+
+        let e = world.entity(a).unwrap();
+        assert!(!e.contains::<Mesh>());
+        assert_eq!(
+            Some(Mesh(1)),
+            e.borrow_inherited::<Mesh>().as_deref().copied()
+        );
+        assert_eq!(
+            Some(Health(10)),
+            e.borrow_inherited::<Health>().as_deref().copied()
+        );
+        let e = world.entity(b).unwrap();
+        assert_eq!(
+            Some(Mesh(2)),
+            e.borrow_inherited::<Mesh>().as_deref().copied()
+        );
+
+        // changes of the prefab are visible, when not overridden
+        *world.entity_mut(tree).unwrap().get_mut::<Mesh>().unwrap() = Mesh(3);
+        assert_eq!(
+            Some(Mesh(3)),
+            world
+                .entity(a)
+                .unwrap()
+                .borrow_inherited::<Mesh>()
+                .as_deref()
+                .copied()
+        );
+        assert_eq!(
+            Some(Mesh(2)),
+            world
+                .entity(b)
+                .unwrap()
+                .borrow_inherited::<Mesh>()
+                .as_deref()
+                .copied()
+        );
+
+        // explicit override (`Health` is not clonable)
+        world.entity_mut(a).unwrap().override_inherited::<Health>();
+        world.entity_mut(a).unwrap().get_mut::<Health>().unwrap().0 -= 1;
+        assert_eq!(
+            Some(Health(9)),
+            world
+                .entity(a)
+                .unwrap()
+                .borrow::<Health>()
+                .as_deref()
+                .copied()
+        );
+        assert_eq!(
+            Some(Health(10)),
+            world
+                .entity(b)
+                .unwrap()
+                .borrow_inherited::<Health>()
+                .as_deref()
+                .copied()
+        );
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let tree = world.spawn_prefab().insert(Scale(1)).insert(Mesh(1)).id();
+        let big_tree = world.instantiate(tree).unwrap().insert(Prefab).id();
+        let a = world.instantiate(big_tree).unwrap().id();
+        let b = world.instantiate(big_tree).unwrap().id();
+
+        // clonable components are copied, before they are changed
+        world.entity_mut(a).unwrap().get_mut::<Scale>().unwrap().0 = 2;
+        assert!(world.entity(a).unwrap().contains::<Scale>());
+        assert!(!world.entity(b).unwrap().contains::<Scale>());
+        assert_eq!(
+            Some(Scale(1)),
+            world
+                .entity(tree)
+                .unwrap()
+                .borrow::<Scale>()
+                .as_deref()
+                .copied()
+        );
+        assert_eq!(
+            Some(Scale(2)),
+            world
+                .entity(a)
+                .unwrap()
+                .borrow::<Scale>()
+                .as_deref()
+                .copied()
+        );
+
+        // other components are not inherited by `get_mut`
+        assert!(world.entity_mut(a).unwrap().get_mut::<Mesh>().is_none());
+    }
+
+    #[test]
+    fn test_instantiate_copy() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let tree = world
+            .spawn_prefab()
+            .insert(Scale(1))
+            .insert(Name("tree"))
+            .insert(Mesh(1))
+            .id();
+        let a = world.instantiate_copy(tree).unwrap().id();
+        let b = world.instantiate(tree).unwrap().id();
+
+        let mut e = world.entity_mut(tree).unwrap();
+        e.get_mut::<Scale>().unwrap().0 = 2;
+        e.get_mut::<Name>().unwrap().0 = "big tree";
+        e.get_mut::<Mesh>().unwrap().0 = 2;
+        drop(e);
+
+        // the copies don't change with the prefab
+        let e = world.entity(a).unwrap();
+        assert!(!e.contains::<Prefab>());
+        assert_eq!(Some(Scale(1)), e.borrow::<Scale>().as_deref().copied());
+        assert_eq!(Some(Name("tree")), e.borrow::<Name>().as_deref().copied());
+        // not clonable, so still shared
+        assert!(!e.contains::<Mesh>());
+        assert_eq!(
+            Some(Mesh(2)),
+            e.borrow_inherited::<Mesh>().as_deref().copied()
+        );
+
+        let e = world.entity(b).unwrap();
+        assert!(!e.contains::<Scale>());
+        assert_eq!(
+            Some(Scale(2)),
+            e.borrow_inherited::<Scale>().as_deref().copied()
+        );
+    }
+
+    #[test]
+    fn test_inherit_query() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let tree = world.spawn_prefab().insert(Mesh(1)).id();
+        let big_tree = world.instantiate(tree).unwrap().insert(Prefab).id();
+        let a = world.instantiate(tree).unwrap().id();
+        let b = world.instantiate(big_tree).unwrap().id();
+        let c = world.instantiate(big_tree).unwrap().insert(Mesh(2)).id();
+        let d = world.spawn().insert(Mesh(4)).id();
+        world.spawn().insert(Health(1));
+        drop(world);
+
+        let mut query = Query::<Without<&Prefab, (Entity, Inherit<Mesh>)>>::new(&mut resources);
+        let mut result: Vec<_> = query.iter().map(|(e, m)| (e, *m)).collect();
+        result.sort_unstable_by_key(|(e, _)| *e);
+        assert_eq!(
+            vec![(a, Mesh(1)), (b, Mesh(1)), (c, Mesh(2)), (d, Mesh(4))],
+            result
+        );
+        drop(query);
+
+        let mut world = resources.world_mut();
+        world.entity_mut(tree).unwrap().get_mut::<Mesh>().unwrap().0 = 3;
+        drop(world);
+
+        let mut query = Query::<Without<&Prefab, (Entity, Inherit<Mesh>)>>::new(&mut resources);
+        let mut result: Vec<_> = query.iter().map(|(e, m)| (e, *m)).collect();
+        result.sort_unstable_by_key(|(e, _)| *e);
+        assert_eq!(
+            vec![(a, Mesh(3)), (b, Mesh(3)), (c, Mesh(2)), (d, Mesh(4))],
+            result
+        );
+    }
+}
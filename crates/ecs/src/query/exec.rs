@@ -1,51 +1,144 @@
-use std::pin::Pin;
+use std::{
+    borrow::Cow,
+    iter::Copied,
+    ops::Deref,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+};
 
 use pulz_schedule::system::data::SystemDataFetch;
 
 use super::QueryParamState;
 use crate::{
     archetype::{Archetype, ArchetypeId, ArchetypeSet, ArchetypeSetIter},
-    entity::Entity,
-    query::{QueryItem, QueryParam, QueryParamFetch, QueryState},
-    resource::{Res, ResourceAccess, ResourceId, Resources},
+    entity::{Entity, UniqueEntities},
+    query::{
+        QueryChunk, QueryItem, QueryParam, QueryParamChunkFetch, QueryParamFetch, QueryState,
+        ReadOnlyQueryParam,
+    },
+    resource::{AccessPartition, Res, ResourceAccess, ResourceId, Resources},
     system::data::{SystemData, SystemDataState},
     WorldInner,
 };
 
 pub struct Query<'w, Q>
 where
-    Q: QueryParam + 'w,
+    Q: QueryParam,
 {
+    res: &'w Resources,
     world: Res<'w, WorldInner>,
-    state: Res<'w, QueryState<Q::State>>,
-    fetch: Q::Fetch<'w>,
+    state: QueryStateRef<'w, Q>,
+    matching_archetypes: Arc<ArchetypeSet>,
+    /// released while a lens of the query is alive
+    fetch: Option<Q::Fetch<'w>>,
+}
+
+/// The state of a query is either borrowed (from a system or a cache), or
+/// owned by the query.
+enum QueryStateRef<'w, Q: QueryParam> {
+    Borrowed(&'w QueryState<Q>),
+    Owned(Box<QueryState<Q>>),
+}
+
+impl<Q: QueryParam> Deref for QueryStateRef<'_, Q> {
+    type Target = QueryState<Q>;
+    #[inline]
+    fn deref(&self) -> &QueryState<Q> {
+        match self {
+            Self::Borrowed(state) => state,
+            Self::Owned(state) => state,
+        }
+    }
 }
 
 pub struct QueryIter<'w, 'a, Q>
 where
-    Q: QueryParam + 'a,
+    Q: QueryParam,
 {
     world: &'a WorldInner,
-    state: &'a QueryState<Q::State>,
+    state: &'a QueryState<Q>,
     fetch: &'a mut Q::Fetch<'w>,
     cursor: Cursor<'a>,
 }
 
 pub struct QueryIntoIter<'w, Q>
 where
-    Q: QueryParam + 'w,
+    Q: QueryParam,
 {
     world: Pin<Res<'w, WorldInner>>,
-    state: Pin<Res<'w, QueryState<Q::State>>>,
+    state: Pin<QueryStateRef<'w, Q>>,
+    _matching_archetypes: Arc<ArchetypeSet>,
     fetch: Q::Fetch<'w>,
     cursor: Cursor<'w>,
 }
 
-struct Cursor<'a> {
-    matching_archetypes: ArchetypeSetIter<'a>,
-    current_archetype_id: ArchetypeId,
-    current_archetype_len: usize,
-    current_archetype_index: usize,
+pub struct QueryManyIter<'w, 'a, Q, I>
+where
+    Q: QueryParam,
+{
+    world: &'a WorldInner,
+    state: &'a QueryState<Q>,
+    matching_archetypes: &'a ArchetypeSet,
+    fetch: &'a mut Q::Fetch<'w>,
+    cursor: ManyCursor<I>,
+}
+
+pub struct QueryManyUniqueIter<'w, 'a, Q>
+where
+    Q: QueryParam,
+{
+    world: &'a WorldInner,
+    state: &'a QueryState<Q>,
+    matching_archetypes: &'a ArchetypeSet,
+    fetch: &'a mut Q::Fetch<'w>,
+    cursor: ManyCursor<Copied<std::slice::Iter<'a, Entity>>>,
+}
+
+/// The position of a [`QueryCursorIter`], which can be stored between frames
+/// (for example in a [`Local`](crate::local::Local)), to process the entities
+/// of a query in batches.
+///
+/// The position is recorded as an archetype and an index into this
+/// archetype, so entities that are moved or removed in the meantime may be
+/// skipped or visited twice in the same cycle.
+#[derive(Clone, Debug)]
+pub struct QueryCursor {
+    archetype_id: ArchetypeId,
+    index: usize,
+    archetype_len: usize,
+    offset: usize,
+    total_len: usize,
+}
+
+pub struct QueryCursorIter<'w, 'a, Q>
+where
+    Q: QueryParam,
+{
+    world: &'a WorldInner,
+    state: &'a QueryState<Q>,
+    matching_archetypes: &'a ArchetypeSet,
+    fetch: &'a mut Q::Fetch<'w>,
+    cursor: &'a mut QueryCursor,
+    start: (ArchetypeId, usize),
+    wrapped: bool,
+}
+
+enum Cursor<'a> {
+    /// Iterates all entities of the matching archetypes.
+    Archetypes {
+        matching_archetypes: ArchetypeSetIter<'a>,
+        current_archetype_id: ArchetypeId,
+        current_archetype_len: usize,
+        current_archetype_index: usize,
+    },
+    /// Iterates the entities of the smallest sparse set required by the query.
+    Sparse {
+        matching_archetypes: &'a ArchetypeSet,
+        /// the buffer of the query state (see [`Cursor::release`])
+        entities: Vec<Entity>,
+        next_index: usize,
+        current_archetype_id: Option<ArchetypeId>,
+    },
 }
 
 impl<'w, Q> Query<'w, Q>
@@ -53,34 +146,65 @@ where
     Q: QueryParam + 'w,
 {
     pub(crate) fn new(res: &'w mut Resources) -> Self {
-        let state_resource_id = res.init::<QueryState<Q::State>>();
-        Self::new_id(res, state_resource_id)
+        let state = QueryState::new(res);
+        let world = res.borrow_res_id(state.world_resource_id).unwrap();
+        Self::new_state(res, world, QueryStateRef::Owned(Box::new(state)))
     }
 
-    fn new_id(res: &'w Resources, resource_id: ResourceId<QueryState<Q::State>>) -> Self {
-        let state = res.borrow_res_id(resource_id).expect("query-state");
+    /// Creates a query with a state borrowed from outside of a system.
+    pub(crate) fn from_state(res: &'w Resources, state: &'w QueryState<Q>) -> Self {
         let world = res.borrow_res_id(state.world_resource_id).unwrap();
-        state.update_archetypes(&world);
+        Self::new_state(res, world, QueryStateRef::Borrowed(state))
+    }
+
+    /// Creates a read-only query from a shared reference to the world.
+    pub(crate) fn from_world(res: &'w Resources, world: Res<'w, WorldInner>) -> Self
+    where
+        Q: ReadOnlyQueryParam,
+    {
+        let world_resource_id = res.expect_id::<WorldInner>();
+        let state = QueryState::from_world(res, &world, world_resource_id);
+        Self::new_state(res, world, QueryStateRef::Owned(Box::new(state)))
+    }
+
+    fn new_state(
+        res: &'w Resources,
+        world: Res<'w, WorldInner>,
+        state: QueryStateRef<'w, Q>,
+    ) -> Self {
+        let matching_archetypes = state.update_archetypes(&world);
         let fetch = Q::Fetch::fetch(res.as_send(), &state.param_state);
         Self {
+            res,
             state,
             world,
-            fetch,
+            matching_archetypes,
+            fetch: Some(fetch),
         }
     }
 
+    /// Returns the fetch of the query, that is borrowed again after a lens
+    /// has released it.
+    #[inline]
+    fn fetch_mut<'a>(
+        res: &'w Resources,
+        state: &QueryState<Q>,
+        fetch: &'a mut Option<Q::Fetch<'w>>,
+    ) -> &'a mut Q::Fetch<'w> {
+        fetch.get_or_insert_with(|| Q::Fetch::fetch(res.as_send(), &state.param_state))
+    }
+
     #[inline]
     pub fn iter<'a>(&'a mut self) -> QueryIter<'w, 'a, Q> {
         let world = &self.world;
         let state = &self.state;
-        let matching_archetypes: *const _ = state.matching_archetypes();
-        let fetch = &mut self.fetch;
+        let fetch = Self::fetch_mut(self.res, state, &mut self.fetch);
+        let cursor = Cursor::new(world, &self.matching_archetypes, state, fetch);
         QueryIter {
             world,
             state,
             fetch,
-            // SAFETY: self reference to state
-            cursor: Cursor::new(unsafe { &*matching_archetypes }),
+            cursor,
         }
     }
 
@@ -93,26 +217,414 @@ where
         }
     }
 
+    /// Calls `f` once for every non-empty matching archetype, with the items
+    /// of all its entities as contiguous slices.
+    ///
+    /// Only available, when all components of the query are stored in a
+    /// [`ColumnStorage`](crate::storage::ColumnStorage).
+    ///
+    /// # Panics
+    /// Panics when called on the query of a lens over a query with sparse
+    /// components, because its archetypes are only partially matched.
+    pub fn for_each_chunk<'a, F>(&'a mut self, mut f: F)
+    where
+        Q::Fetch<'w>: QueryParamChunkFetch<'w>,
+        F: FnMut(QueryChunk<'w, 'a, Q>),
+    {
+        let world: &'a WorldInner = &self.world;
+        let state: &'a QueryState<Q> = &self.state;
+        assert!(
+            state.lens_entities.is_none(),
+            "chunks are not available for a lens over sparse components"
+        );
+        let fetch: *mut Q::Fetch<'w> = Self::fetch_mut(self.res, state, &mut self.fetch);
+        for archetype_id in self.matching_archetypes.iter() {
+            let archetype = &world.archetypes[archetype_id];
+            if archetype.is_empty() {
+                continue;
+            }
+            // SAFETY: chunks of different archetypes are disjoint
+            let fetch = unsafe { &mut *fetch };
+            fetch.set_archetype(&state.param_state, archetype);
+            f(fetch.get_chunk(archetype));
+        }
+    }
+
+    /// Returns the items of the given entities. Entities that don't match the
+    /// query are skipped (see [`QueryManyIter::mismatched`]).
+    ///
+    /// The items are fetched with [`QueryManyIter::fetch_next`], because the
+    /// list may contain duplicates. Use [`iter_many_mut`](Self::iter_many_mut)
+    /// for an [`Iterator`] over exclusive items.
+    #[inline]
+    pub fn iter_many<'a, I>(&'a mut self, entities: I) -> QueryManyIter<'w, 'a, Q, I::IntoIter>
+    where
+        I: IntoIterator<Item = Entity>,
+    {
+        QueryManyIter {
+            world: &self.world,
+            state: &self.state,
+            matching_archetypes: &self.matching_archetypes,
+            fetch: Self::fetch_mut(self.res, &self.state, &mut self.fetch),
+            cursor: ManyCursor::new(entities.into_iter()),
+        }
+    }
+
+    /// Returns an iterator over the items of the given unique entities.
+    /// Entities that don't match the query are skipped (see
+    /// [`QueryManyUniqueIter::mismatched`]).
+    #[inline]
+    pub fn iter_many_mut<'a>(
+        &'a mut self,
+        entities: &'a UniqueEntities,
+    ) -> QueryManyUniqueIter<'w, 'a, Q> {
+        QueryManyUniqueIter {
+            world: &self.world,
+            state: &self.state,
+            matching_archetypes: &self.matching_archetypes,
+            fetch: Self::fetch_mut(self.res, &self.state, &mut self.fetch),
+            cursor: ManyCursor::new(entities.iter().copied()),
+        }
+    }
+
+    /// Returns an iterator over the items of this query, that starts at the
+    /// position of the `cursor`, and wraps around at the end of the query.
+    /// The iteration ends after one full cycle, and the `cursor` is advanced
+    /// past every returned item.
+    ///
+    /// Combined with [`Iterator::take`], this allows processing a large query
+    /// in batches over multiple frames.
+    pub fn iter_from<'a>(&'a mut self, cursor: &'a mut QueryCursor) -> QueryCursorIter<'w, 'a, Q> {
+        let world: &'a WorldInner = &self.world;
+        let mut offset = 0;
+        let mut total_len = 0;
+        for archetype_id in self.matching_archetypes.iter() {
+            let len = world.archetypes[archetype_id].len();
+            if archetype_id.index() < cursor.archetype_id.index() {
+                offset += len;
+            }
+            total_len += len;
+        }
+        cursor.offset = offset;
+        cursor.total_len = total_len;
+        cursor.archetype_len = 0;
+        if self.matching_archetypes.contains(cursor.archetype_id) {
+            let archetype = &world.archetypes[cursor.archetype_id];
+            cursor.archetype_len = archetype.len();
+            if cursor.index < cursor.archetype_len {
+                Self::fetch_mut(self.res, &self.state, &mut self.fetch)
+                    .set_archetype(&self.state.param_state, archetype);
+            }
+        }
+        QueryCursorIter {
+            world,
+            state: &self.state,
+            matching_archetypes: &self.matching_archetypes,
+            fetch: Self::fetch_mut(self.res, &self.state, &mut self.fetch),
+            start: (cursor.archetype_id, cursor.index),
+            cursor,
+            wrapped: false,
+        }
+    }
+
+    /// Creates a lens with a different [`QueryParam`] over the entities that
+    /// are matched by this query.
+    ///
+    /// # Panics
+    /// Panics when `NewQ` requires access to components, that this query
+    /// doesn't have access to (for example `&mut T` from a `&T`).
+    pub fn transmute_lens<NewQ>(&mut self) -> QueryLens<'_, NewQ>
+    where
+        NewQ: QueryParam,
+    {
+        let lens_entities = self.lens_entities();
+        // the lens borrows the storages itself
+        self.fetch = None;
+        QueryLens::new(
+            self.res,
+            self.state.world_resource_id,
+            &self.state.access,
+            self.state.validated,
+            &self.matching_archetypes,
+            lens_entities,
+            || self.state.param_state.type_name(),
+        )
+    }
+
+    /// Creates a lens with a different [`QueryParam`] over the entities that
+    /// are matched by both, this query and `other`.
+    ///
+    /// # Panics
+    /// Panics when `NewQ` requires access to components, that neither query
+    /// has access to, or when the queries are from different worlds.
+    pub fn join<'a, Other, NewQ>(
+        &'a mut self,
+        other: &'a mut Query<'_, Other>,
+    ) -> QueryLens<'a, NewQ>
+    where
+        Other: QueryParam,
+        NewQ: QueryParam,
+    {
+        assert_eq!(
+            self.state.world_resource_id, other.state.world_resource_id,
+            "queries are from different worlds"
+        );
+        let mut access = ResourceAccess::new();
+        access.extend(&self.state.access);
+        access.extend(&other.state.access);
+        let mut matching_archetypes = ArchetypeSet::clone(&self.matching_archetypes);
+        matching_archetypes.retain_set(&other.matching_archetypes);
+        let lens_entities = match (self.lens_entities(), other.lens_entities()) {
+            (Some(mut a), Some(b)) => {
+                a.retain(|entity| b.binary_search(entity).is_ok());
+                Some(a)
+            }
+            (a, b) => a.or(b),
+        };
+        // the lens borrows the storages itself
+        self.fetch = None;
+        other.fetch = None;
+        QueryLens::new(
+            self.res,
+            self.state.world_resource_id,
+            &access,
+            self.state.validated && other.state.validated,
+            &matching_archetypes,
+            lens_entities,
+            || {
+                format!(
+                    "{} + {}",
+                    self.state.param_state.type_name(),
+                    other.state.param_state.type_name()
+                )
+                .into()
+            },
+        )
+    }
+
+    /// Collects the (sorted) entities of this query for a lens, when the
+    /// matching archetypes alone are not sufficient, because the query
+    /// requires sparse components.
+    fn lens_entities(&mut self) -> Option<Vec<Entity>> {
+        let fetch = Self::fetch_mut(self.res, &self.state, &mut self.fetch);
+        if fetch.sparse_len().is_none() && self.state.lens_entities.is_none() {
+            return None;
+        }
+        let mut entities = Vec::new();
+        let mut cursor = Cursor::new(&self.world, &self.matching_archetypes, &self.state, fetch);
+        while let Some((archetype, index)) = cursor.next(&self.world, &self.state, fetch) {
+            entities.push(archetype.entities[index]);
+        }
+        cursor.release(&self.state.sparse_entities);
+        entities.sort_unstable();
+        Some(entities)
+    }
+
     pub fn get<'a>(&'a mut self, entity: Entity) -> Option<QueryItem<'w, 'a, Q>> {
         let location = self.world.entities.get(entity)?;
-        if !self
-            .state
-            .matching_archetypes()
-            .contains(location.archetype_id)
-        {
+        if !self.matching_archetypes.contains(location.archetype_id) {
             return None;
         }
         let archetype = &self.world.archetypes[location.archetype_id];
-        self.fetch.set_archetype(&self.state.param_state, archetype);
-        let item = self.fetch.get(archetype, location.index);
+        let fetch = Self::fetch_mut(self.res, &self.state, &mut self.fetch);
+        fetch.set_archetype(&self.state.param_state, archetype);
+        if !self.state.matches_entity(fetch, archetype, location.index) {
+            return None;
+        }
+        let item = fetch.get(archetype, location.index);
         Some(item)
     }
 }
 
-impl<'a> Cursor<'a> {
+/// A query with a different [`QueryParam`] over the entities of other
+/// queries. Created by [`Query::transmute_lens`] and [`Query::join`].
+pub struct QueryLens<'a, Q>
+where
+    Q: QueryParam,
+{
+    res: &'a Resources,
+    world: Res<'a, WorldInner>,
+    state: QueryState<Q>,
+}
+
+impl<'a, Q> QueryLens<'a, Q>
+where
+    Q: QueryParam,
+{
+    fn new(
+        res: &'a Resources,
+        world_resource_id: ResourceId<WorldInner>,
+        access: &ResourceAccess,
+        validated: bool,
+        matching_archetypes: &ArchetypeSet,
+        lens_entities: Option<Vec<Entity>>,
+        source_name: impl FnOnce() -> Cow<'static, str>,
+    ) -> Self {
+        let world = res.borrow_res_id(world_resource_id).unwrap();
+        let mut state = QueryState::<Q>::from_world(res, &world, world_resource_id);
+        if !state.access.is_subset(access) {
+            panic!(
+                "query `{}` requires access that is not granted by `{}`",
+                state.param_state.type_name(),
+                source_name()
+            );
+        }
+        state.restrict_archetypes(matching_archetypes);
+        state.lens_entities = lens_entities;
+        if validated {
+            // the lens only accesses the entities of the validated queries
+            state.access_validated();
+        }
+        Self { res, world, state }
+    }
+
+    /// Returns the query of this lens.
     #[inline]
-    fn new(matching_archetypes: &'a ArchetypeSet) -> Self {
+    pub fn query(&mut self) -> Query<'_, Q> {
+        Query::new_state(
+            self.res,
+            Res::clone(&self.world),
+            QueryStateRef::Borrowed(&self.state),
+        )
+    }
+}
+
+impl QueryCursor {
+    /// Creates a cursor at the start of a query.
+    #[inline]
+    pub const fn new() -> Self {
         Self {
+            archetype_id: ArchetypeId::EMPTY,
+            index: 0,
+            archetype_len: 0,
+            offset: 0,
+            total_len: 0,
+        }
+    }
+
+    /// Moves the cursor back to the start of a query.
+    #[inline]
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// The approximate fraction of the current cycle that has been processed,
+    /// in the range `0.0..=1.0`.
+    ///
+    /// The value is based on the number of entities of the query, when the
+    /// last iterator was created by [`Query::iter_from`].
+    pub fn progress(&self) -> f32 {
+        if self.total_len == 0 {
+            return 0.0;
+        }
+        let processed = self.offset + self.index.min(self.archetype_len);
+        (processed as f32 / self.total_len as f32).min(1.0)
+    }
+
+    /// Advances to the next matching entity, and wraps around at the end.
+    /// Returns `None` after a full cycle (when `start` is reached again).
+    fn next<'a, 'w, Q>(
+        &mut self,
+        world: &'a WorldInner,
+        matching_archetypes: &ArchetypeSet,
+        start: (ArchetypeId, usize),
+        wrapped: &mut bool,
+        state: &QueryState<Q>,
+        fetch: &mut Q::Fetch<'w>,
+    ) -> Option<(&'a Archetype, usize)>
+    where
+        Q: QueryParam,
+    {
+        loop {
+            if *wrapped && (self.archetype_id.index(), self.index) >= (start.0.index(), start.1) {
+                return None;
+            }
+            if self.index < self.archetype_len {
+                let archetype = &world.archetypes[self.archetype_id];
+                let archetype_index = self.index;
+                self.index += 1;
+                if state.matches_entity(fetch, archetype, archetype_index) {
+                    return Some((archetype, archetype_index));
+                }
+            } else {
+                let next_archetype_id = match matching_archetypes.find_next(self.archetype_id) {
+                    Some(id) => {
+                        self.offset += self.archetype_len;
+                        id
+                    }
+                    None if *wrapped => return None,
+                    None => {
+                        *wrapped = true;
+                        self.offset = 0;
+                        matching_archetypes.first()?
+                    }
+                };
+                let archetype = &world.archetypes[next_archetype_id];
+                self.archetype_id = next_archetype_id;
+                self.index = 0;
+                self.archetype_len = archetype.len();
+                if !archetype.is_empty() {
+                    fetch.set_archetype(&state.param_state, archetype);
+                }
+            }
+        }
+    }
+}
+
+impl Default for QueryCursor {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Cursor<'a> {
+    /// Chooses the cheaper driver for the iteration: either all entities of
+    /// the matching archetypes, or the entities of the smallest sparse set
+    /// that is required by the query.
+    fn new<'w, Q>(
+        world: &WorldInner,
+        matching_archetypes: &'a ArchetypeSet,
+        state: &QueryState<Q>,
+        fetch: &Q::Fetch<'w>,
+    ) -> Self
+    where
+        Q: QueryParam,
+    {
+        let sparse_len = fetch.sparse_len();
+        let lens_len = state.lens_entities.as_ref().map(Vec::len);
+        let driving_len = match (sparse_len, lens_len) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        if let Some(driving_len) = driving_len {
+            let archetypes_len: usize = matching_archetypes
+                .iter()
+                .map(|id| world.archetypes[id].len())
+                .sum();
+            if driving_len < archetypes_len {
+                let mut entities = std::mem::take(
+                    &mut *state
+                        .sparse_entities
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner),
+                );
+                entities.clear();
+                match state.lens_entities {
+                    Some(ref lens_entities) if lens_len == Some(driving_len) => {
+                        entities.extend_from_slice(lens_entities)
+                    }
+                    _ => fetch.collect_sparse_entities(&mut entities),
+                }
+                return Self::Sparse {
+                    matching_archetypes,
+                    entities,
+                    next_index: 0,
+                    current_archetype_id: None,
+                };
+            }
+        }
+        Self::Archetypes {
             matching_archetypes: matching_archetypes.iter(),
             current_archetype_id: ArchetypeId::EMPTY,
             current_archetype_len: 0,
@@ -120,21 +632,149 @@ impl<'a> Cursor<'a> {
         }
     }
 
-    fn next(&mut self, world: &'a WorldInner) -> Option<(&'a Archetype, usize)> {
-        loop {
-            if self.current_archetype_index < self.current_archetype_len {
-                let archetype = &world.archetypes[self.current_archetype_id];
-                let archetype_index = self.current_archetype_index;
-                self.current_archetype_index += 1;
-                return Some((archetype, archetype_index));
-            } else {
-                // reached end, or initial state
-                self.current_archetype_id = self.matching_archetypes.next()?;
-                let archetype = &world.archetypes[self.current_archetype_id];
-                self.current_archetype_index = 0;
-                self.current_archetype_len = archetype.len();
+    /// Hands the entity buffer of a sparse cursor back to the query state,
+    /// so the next iteration can reuse it.
+    fn release(&mut self, buffer: &Mutex<Vec<Entity>>) {
+        if let Self::Sparse { entities, .. } = self {
+            let mut buffer = buffer.lock().unwrap_or_else(PoisonError::into_inner);
+            if buffer.capacity() < entities.capacity() {
+                *buffer = std::mem::take(entities);
+            }
+        }
+    }
+
+    /// Advances to the next matching entity and updates the archetype of
+    /// `fetch` when it has changed.
+    fn next<'w, Q>(
+        &mut self,
+        world: &'a WorldInner,
+        state: &QueryState<Q>,
+        fetch: &mut Q::Fetch<'w>,
+    ) -> Option<(&'a Archetype, usize)>
+    where
+        Q: QueryParam,
+    {
+        match self {
+            Self::Archetypes {
+                matching_archetypes,
+                current_archetype_id,
+                current_archetype_len,
+                current_archetype_index,
+            } => loop {
+                if *current_archetype_index < *current_archetype_len {
+                    let archetype = &world.archetypes[*current_archetype_id];
+                    let archetype_index = *current_archetype_index;
+                    *current_archetype_index += 1;
+                    if state.matches_entity(fetch, archetype, archetype_index) {
+                        return Some((archetype, archetype_index));
+                    }
+                } else {
+                    // reached end, or initial state
+                    *current_archetype_id = matching_archetypes.next()?;
+                    let archetype = &world.archetypes[*current_archetype_id];
+                    *current_archetype_index = 0;
+                    *current_archetype_len = archetype.len();
+                    if !archetype.is_empty() {
+                        fetch.set_archetype(&state.param_state, archetype);
+                    }
+                }
+            },
+            Self::Sparse {
+                matching_archetypes,
+                entities,
+                next_index,
+                current_archetype_id,
+            } => loop {
+                let &entity = entities.get(*next_index)?;
+                *next_index += 1;
+                if let Some(result) = locate_entity(
+                    world,
+                    matching_archetypes,
+                    current_archetype_id,
+                    state,
+                    fetch,
+                    entity,
+                ) {
+                    return Some(result);
+                }
+            },
+        }
+    }
+}
+
+/// Looks up the location of the entity, when it matches the query, and
+/// updates the archetype of `fetch` when it has changed.
+fn locate_entity<'a, 'w, Q>(
+    world: &'a WorldInner,
+    matching_archetypes: &ArchetypeSet,
+    current_archetype_id: &mut Option<ArchetypeId>,
+    state: &QueryState<Q>,
+    fetch: &mut Q::Fetch<'w>,
+    entity: Entity,
+) -> Option<(&'a Archetype, usize)>
+where
+    Q: QueryParam,
+{
+    let location = world.entities.get(entity)?;
+    if !matching_archetypes.contains(location.archetype_id) {
+        return None;
+    }
+    let archetype = &world.archetypes[location.archetype_id];
+    if *current_archetype_id != Some(location.archetype_id) {
+        *current_archetype_id = Some(location.archetype_id);
+        fetch.set_archetype(&state.param_state, archetype);
+    }
+    if state.matches_entity(fetch, archetype, location.index) {
+        Some((archetype, location.index))
+    } else {
+        None
+    }
+}
+
+/// Cursor over an explicit list of entities
+struct ManyCursor<I> {
+    entities: I,
+    current_archetype_id: Option<ArchetypeId>,
+    mismatched: Vec<Entity>,
+}
+
+impl<I> ManyCursor<I>
+where
+    I: Iterator<Item = Entity>,
+{
+    #[inline]
+    fn new(entities: I) -> Self {
+        Self {
+            entities,
+            current_archetype_id: None,
+            mismatched: Vec::new(),
+        }
+    }
+
+    fn next<'a, 'w, Q>(
+        &mut self,
+        world: &'a WorldInner,
+        matching_archetypes: &ArchetypeSet,
+        state: &QueryState<Q>,
+        fetch: &mut Q::Fetch<'w>,
+    ) -> Option<(&'a Archetype, usize)>
+    where
+        Q: QueryParam,
+    {
+        for entity in self.entities.by_ref() {
+            if let Some(result) = locate_entity(
+                world,
+                matching_archetypes,
+                &mut self.current_archetype_id,
+                state,
+                fetch,
+                entity,
+            ) {
+                return Some(result);
             }
+            self.mismatched.push(entity);
         }
+        None
     }
 }
 
@@ -161,19 +801,24 @@ where
     #[inline]
     fn into_iter(self) -> Self::IntoIter {
         let Self {
+            res,
             world,
             state,
+            matching_archetypes,
             fetch,
         } = self;
+        let fetch = fetch.unwrap_or_else(|| Q::Fetch::fetch(res.as_send(), &state.param_state));
         let world = unsafe { Pin::new_unchecked(world) };
         let state = unsafe { Pin::new_unchecked(state) };
-        let matching_archetypes: *const _ = state.matching_archetypes();
+        let matching_archetypes_ptr: *const ArchetypeSet = &*matching_archetypes;
+        // safety: self-reference into the shared set, that doesn't move with the `Arc`
+        let cursor = Cursor::new(&world, unsafe { &*matching_archetypes_ptr }, &state, &fetch);
         QueryIntoIter {
             world,
             state,
+            _matching_archetypes: matching_archetypes,
             fetch,
-            // safety: self-referenceto state; state is pinned
-            cursor: Cursor::new(unsafe { &*matching_archetypes }),
+            cursor,
         }
     }
 }
@@ -188,17 +833,22 @@ where
     fn next(&mut self) -> Option<Self::Item> {
         let fetch: *mut _ = self.fetch;
         let fetch = unsafe { &mut *fetch }; // found no better way to deal with the lifetimes
-        let Some((archetype, index)) = self.cursor.next(self.world) else {
-            return None;
-        };
-        if index == 0 {
-            fetch.set_archetype(&self.state.param_state, archetype);
-        }
+        let (archetype, index) = self.cursor.next(self.world, self.state, fetch)?;
         let item = fetch.get(archetype, index);
         Some(item)
     }
 }
 
+impl<'w, 'a, Q> Drop for QueryIter<'w, 'a, Q>
+where
+    Q: QueryParam,
+{
+    #[inline]
+    fn drop(&mut self) {
+        self.cursor.release(&self.state.sparse_entities);
+    }
+}
+
 impl<'w, Q> Iterator for QueryIntoIter<'w, Q>
 where
     Q: QueryParam + 'w,
@@ -211,54 +861,173 @@ where
         let world = unsafe { &*world }; // found no better way to deal with the lifetimes
         let fetch: *mut _ = &mut self.fetch;
         let fetch = unsafe { &mut *fetch }; // found no better way to deal with the lifetimes
-        let Some((archetype, index)) = self.cursor.next(world) else {
-            return None;
-        };
-        if index == 0 {
-            fetch.set_archetype(&self.state.param_state, archetype);
-        }
+        let (archetype, index) = self.cursor.next(world, &self.state, fetch)?;
+        let item = fetch.get(archetype, index);
+        Some(item)
+    }
+}
+
+impl<'w, Q> Drop for QueryIntoIter<'w, Q>
+where
+    Q: QueryParam,
+{
+    #[inline]
+    fn drop(&mut self) {
+        self.cursor.release(&self.state.sparse_entities);
+    }
+}
+
+impl<'w: 'a, 'a, Q> Iterator for QueryCursorIter<'w, 'a, Q>
+where
+    Q: QueryParam + 'a,
+{
+    type Item = QueryItem<'w, 'a, Q>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let fetch: *mut _ = self.fetch;
+        // SAFETY: every entity is visited at most once per cycle
+        let fetch = unsafe { &mut *fetch };
+        let (archetype, index) = self.cursor.next(
+            self.world,
+            self.matching_archetypes,
+            self.start,
+            &mut self.wrapped,
+            self.state,
+            fetch,
+        )?;
+        let item = fetch.get(archetype, index);
+        Some(item)
+    }
+}
+
+impl<'w, 'a, Q, I> QueryManyIter<'w, 'a, Q, I>
+where
+    Q: QueryParam + 'a,
+    I: Iterator<Item = Entity>,
+{
+    /// Returns the item of the next matching entity.
+    ///
+    /// Unlike [`Iterator::next`], the returned item borrows from this
+    /// iterator, so it can't outlive the next call.
+    #[inline]
+    pub fn fetch_next(&mut self) -> Option<QueryItem<'w, '_, Q>> {
+        let (archetype, index) =
+            self.cursor
+                .next(self.world, self.matching_archetypes, self.state, self.fetch)?;
+        Some(self.fetch.get(archetype, index))
+    }
+
+    /// Returns the entities that were skipped so far, because they don't
+    /// exist or don't match the query.
+    #[inline]
+    pub fn mismatched(&self) -> &[Entity] {
+        &self.cursor.mismatched
+    }
+}
+
+impl<'w: 'a, 'a, Q, I> Iterator for QueryManyIter<'w, 'a, Q, I>
+where
+    Q: ReadOnlyQueryParam + 'a,
+    I: Iterator<Item = Entity>,
+{
+    type Item = QueryItem<'w, 'a, Q>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let fetch: *mut _ = self.fetch;
+        // SAFETY: read-only items can alias
+        let fetch = unsafe { &mut *fetch };
+        let (archetype, index) =
+            self.cursor
+                .next(self.world, self.matching_archetypes, self.state, fetch)?;
+        let item = fetch.get(archetype, index);
+        Some(item)
+    }
+}
+
+impl<'a, Q> QueryManyUniqueIter<'_, 'a, Q>
+where
+    Q: QueryParam + 'a,
+{
+    /// Returns the entities that were skipped so far, because they don't
+    /// exist or don't match the query.
+    #[inline]
+    pub fn mismatched(&self) -> &[Entity] {
+        &self.cursor.mismatched
+    }
+}
+
+impl<'w: 'a, 'a, Q> Iterator for QueryManyUniqueIter<'w, 'a, Q>
+where
+    Q: QueryParam + 'a,
+{
+    type Item = QueryItem<'w, 'a, Q>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let fetch: *mut _ = self.fetch;
+        // SAFETY: entities are unique, so items don't alias
+        let fetch = unsafe { &mut *fetch };
+        let (archetype, index) =
+            self.cursor
+                .next(self.world, self.matching_archetypes, self.state, fetch)?;
         let item = fetch.get(archetype, index);
         Some(item)
     }
 }
 
 #[doc(hidden)]
-pub struct QuerySystemParamState<S: QueryParamState>(ResourceId<QueryState<S>>);
+pub struct QuerySystemParamState<Q: QueryParam>(QueryState<Q>, String);
 
 #[doc(hidden)]
-pub struct QuerySystemParamFetch<'r, S: QueryParamState>(&'r Resources, ResourceId<QueryState<S>>);
+pub struct QuerySystemParamFetch<'r, Q: QueryParam>(&'r Resources, &'r QueryState<Q>);
 
 impl<Q> SystemData for Query<'_, Q>
 where
     Q: QueryParam + 'static,
 {
-    type State = QuerySystemParamState<Q::State>;
-    type Fetch<'r> = QuerySystemParamFetch<'r, Q::State>;
+    type State = QuerySystemParamState<Q>;
+    type Fetch<'r> = QuerySystemParamFetch<'r, Q>;
     type Item<'a> = Query<'a, Q>;
 
     fn get<'a>(fetch: &'a mut Self::Fetch<'_>) -> Self::Item<'a> {
-        Query::new_id(fetch.0, fetch.1)
+        let world = fetch.0.borrow_res_id(fetch.1.world_resource_id).unwrap();
+        Query::new_state(fetch.0, world, QueryStateRef::Borrowed(fetch.1))
     }
 }
 
-unsafe impl<S: QueryParamState> SystemDataState for QuerySystemParamState<S> {
+unsafe impl<Q: QueryParam + 'static> SystemDataState for QuerySystemParamState<Q> {
     #[inline]
     fn init(resources: &mut Resources) -> Self {
-        Self(resources.init::<QueryState<S>>())
+        let state = QueryState::<Q>::new(resources);
+        let name = format!("Query<{}>", state.param_state.type_name());
+        Self(state, name)
     }
 
     #[inline]
-    fn update_access(&self, resources: &Resources, access: &mut ResourceAccess) {
-        let state = resources.borrow_res_id(self.0).unwrap();
-        access.add_shared(self.0);
+    fn update_access(&self, _resources: &Resources, access: &mut ResourceAccess) {
+        let state = &self.0;
         access.add_shared(state.world_resource_id);
-        state.param_state.update_access(access)
+        access.extend(&state.access);
+        let mut partition = AccessPartition::new();
+        state.param_state.update_partition(&mut partition);
+        access.set_partition(partition);
+    }
+
+    #[inline]
+    fn access_validated(&mut self) {
+        self.0.access_validated();
+    }
+
+    fn type_name(&self) -> Cow<'static, str> {
+        Cow::Owned(self.1.clone())
     }
 }
 
-impl<'r, S: QueryParamState> SystemDataFetch<'r> for QuerySystemParamFetch<'r, S> {
-    type State = QuerySystemParamState<S>;
+impl<'r, Q: QueryParam + 'static> SystemDataFetch<'r> for QuerySystemParamFetch<'r, Q> {
+    type State = QuerySystemParamState<Q>;
     fn fetch(res: &'r Resources, state: &'r mut Self::State) -> Self {
-        Self(res, state.0)
+        Self(res, &state.0)
     }
 }
@@ -1,12 +1,16 @@
-use pulz_schedule::resource::{ResourceAccess, ResourceId};
+use std::{borrow::Cow, pin::Pin};
+
+use pulz_schedule::resource::{AccessPartition, ResourceAccess, ResourceId};
 
 use crate::{
     archetype::Archetype,
     component::{Component, ComponentId, Components},
     entity::Entity,
-    query::{QueryParam, QueryParamFetch, QueryParamState},
+    query::{
+        QueryParam, QueryParamChunkFetch, QueryParamFetch, QueryParamState, ReadOnlyQueryParam,
+    },
     resource::{Res, ResMut, Resources, ResourcesSend},
-    storage::Storage,
+    storage::{tag_column_mut, tag_mut, tag_ref, ColumnStorage, Storage},
 };
 
 impl<T: Component> QueryParam for &'_ T {
@@ -14,10 +18,14 @@ impl<T: Component> QueryParam for &'_ T {
     type Fetch<'w> = QryRefFetch<'w, T>;
 }
 
+// SAFETY: only shared access
+unsafe impl<T: Component> ReadOnlyQueryParam for &'_ T {}
+
 #[doc(hidden)]
 pub struct QryRefState<T: Component> {
     storage_id: ResourceId<T::Storage>,
     component_id: ComponentId<T>,
+    name: String,
 }
 
 unsafe impl<T: Component> QueryParamState for QryRefState<T> {
@@ -25,26 +33,63 @@ unsafe impl<T: Component> QueryParamState for QryRefState<T> {
     fn init(_res: &Resources, components: &Components) -> Self {
         let component_id = components.expect_id::<T>();
         let component = components.get(component_id).unwrap();
+        assert!(
+            <T::Storage as Storage>::BORROWABLE,
+            "component {} is not stored as a whole and can't be queried with `&`",
+            component.name()
+        );
         Self {
             storage_id: component.storage_id.typed(),
             component_id,
+            name: component.name().to_owned(),
         }
     }
 
+    #[inline]
+    fn is_registered(components: &Components) -> bool {
+        components.id::<T>().is_some()
+    }
+
     #[inline]
     fn update_access(&self, access: &mut ResourceAccess) {
-        access.add_shared_checked(self.storage_id);
+        // tags are not stored
+        if !<T::Storage as Storage>::TAG {
+            access.add_shared_checked(self.storage_id);
+            access.set_name(self.storage_id, self.name.clone());
+            if <T::Storage as Storage>::DISJOINT {
+                access.add_partitioned(self.storage_id);
+            }
+        }
     }
 
     #[inline]
     fn matches_archetype(&self, archetype: &Archetype) -> bool {
         <T::Storage as Storage>::SPARSE || archetype.contains_component_id(self.component_id)
     }
+
+    #[inline]
+    fn update_partition(&self, partition: &mut AccessPartition) {
+        if !<T::Storage as Storage>::SPARSE {
+            partition.with.insert(self.component_id.offset());
+        }
+    }
+
+    fn type_name(&self) -> Cow<'static, str> {
+        format!("&{}", self.name).into()
+    }
 }
 
+/// The storage is not borrowed for tags (see [`Storage::TAG`]).
 #[doc(hidden)]
 #[repr(transparent)]
-pub struct QryRefFetch<'w, T: Component>(Res<'w, T::Storage>);
+pub struct QryRefFetch<'w, T: Component>(Option<Res<'w, T::Storage>>);
+
+impl<T: Component> QryRefFetch<'_, T> {
+    #[inline(always)]
+    fn storage(&self) -> &T::Storage {
+        self.0.as_deref().expect("unable to borrow component")
+    }
+}
 
 impl<'w, T: Component> QueryParamFetch<'w> for QryRefFetch<'w, T> {
     type State = QryRefState<T>;
@@ -52,10 +97,13 @@ impl<'w, T: Component> QueryParamFetch<'w> for QryRefFetch<'w, T> {
 
     #[inline]
     fn fetch(res: &'w ResourcesSend, state: &QryRefState<T>) -> Self {
-        Self(
+        if <T::Storage as Storage>::TAG {
+            return Self(None);
+        }
+        Self(Some(
             res.borrow_res_id(state.storage_id)
                 .expect("unable to borrow component"),
-        )
+        ))
     }
 
     #[inline(always)]
@@ -63,10 +111,50 @@ impl<'w, T: Component> QueryParamFetch<'w> for QryRefFetch<'w, T> {
 
     #[inline]
     fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_> {
-        self.0
+        if <T::Storage as Storage>::TAG {
+            // SAFETY: the archetype contains the tag (`matches_archetype`)
+            return unsafe { tag_ref() };
+        }
+        self.storage()
             .get(archetype.entities[index], archetype.id, index)
             .expect("unable to get component item")
     }
+
+    #[inline]
+    fn matches_entity(&self, archetype: &Archetype, index: usize) -> bool {
+        !<T::Storage as Storage>::SPARSE
+            || self
+                .storage()
+                .contains(archetype.entities[index], archetype.id, index)
+    }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        self.0.as_ref()?.sparse_len()
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        if let Some(storage) = &self.0 {
+            storage.collect_sparse_entities(entities)
+        }
+    }
+}
+
+impl<'w, T: Component> QueryParamChunkFetch<'w> for QryRefFetch<'w, T>
+where
+    T::Storage: ColumnStorage,
+{
+    type Chunk<'a> = &'a [T] where Self: 'a;
+
+    #[inline]
+    fn get_chunk<'a>(&'a mut self, archetype: &'a Archetype) -> Self::Chunk<'a> {
+        if <T::Storage as Storage>::TAG {
+            // SAFETY: the archetype contains the tag (`matches_archetype`)
+            return unsafe { tag_column_mut(archetype.len()) };
+        }
+        self.storage().column(archetype.id)
+    }
 }
 
 impl<T: Component> QueryParam for &'_ mut T {
@@ -78,6 +166,9 @@ impl<T: Component> QueryParam for &'_ mut T {
 pub struct QryRefMutState<T: Component> {
     storage_id: ResourceId<T::Storage>,
     component_id: ComponentId<T>,
+    name: String,
+    /// the access was validated against the other parameters of a system
+    validated: bool,
 }
 
 unsafe impl<T: Component> QueryParamState for QryRefMutState<T> {
@@ -85,26 +176,94 @@ unsafe impl<T: Component> QueryParamState for QryRefMutState<T> {
     fn init(_res: &Resources, components: &Components) -> Self {
         let component_id = components.expect_id::<T>();
         let component = components.get(component_id).unwrap();
+        assert!(
+            <T::Storage as Storage>::BORROWABLE,
+            "component {} is not stored as a whole and can't be queried with `&mut`",
+            component.name()
+        );
+        assert!(
+            <T::Storage as Storage>::MUTABLE,
+            "component {} is immutable and can't be queried with `&mut`",
+            component.name()
+        );
         Self {
             storage_id: component.storage_id.typed(),
             component_id,
+            name: component.name().to_owned(),
+            validated: false,
         }
     }
 
+    #[inline]
+    fn is_registered(components: &Components) -> bool {
+        components.id::<T>().is_some()
+    }
+
     #[inline]
     fn update_access(&self, access: &mut ResourceAccess) {
-        access.add_exclusive_checked(self.storage_id);
+        // tags are not stored
+        if !<T::Storage as Storage>::TAG {
+            access.add_exclusive_checked(self.storage_id);
+            access.set_name(self.storage_id, self.name.clone());
+            if <T::Storage as Storage>::DISJOINT {
+                access.add_partitioned(self.storage_id);
+            }
+        }
+    }
+
+    #[inline]
+    fn access_validated(&mut self) {
+        self.validated = true;
     }
 
     #[inline]
     fn matches_archetype(&self, archetype: &Archetype) -> bool {
         <T::Storage as Storage>::SPARSE || archetype.contains_component_id(self.component_id)
     }
+
+    #[inline]
+    fn update_partition(&self, partition: &mut AccessPartition) {
+        if !<T::Storage as Storage>::SPARSE {
+            partition.with.insert(self.component_id.offset());
+        }
+    }
+
+    fn type_name(&self) -> Cow<'static, str> {
+        format!("&mut {}", self.name).into()
+    }
 }
 
+/// The storage is borrowed exclusively, unless the access of the query was
+/// validated against the other parameters of its system. Then storages,
+/// that support it (see [`Storage::DISJOINT`]), are only borrowed shared, so
+/// queries that can never match the same entities can write the same
+/// storage at the same time.
 #[doc(hidden)]
-#[repr(transparent)]
-pub struct QryRefMutFetch<'w, T: Component>(ResMut<'w, T::Storage>);
+pub enum QryRefMutFetch<'w, T: Component> {
+    Exclusive(ResMut<'w, T::Storage>),
+    Disjoint(Res<'w, T::Storage>),
+}
+
+impl<T: Component> QryRefMutFetch<'_, T> {
+    #[inline(always)]
+    fn storage(&self) -> &T::Storage {
+        match self {
+            Self::Exclusive(storage) => storage,
+            Self::Disjoint(storage) => storage,
+        }
+    }
+
+    /// Returns the components of the archetype, that are written through a
+    /// shared borrow of the storage.
+    #[inline]
+    fn disjoint_column(storage: &T::Storage, archetype: &Archetype) -> *mut T {
+        let (column, len) = storage
+            .column_ptr(archetype.id)
+            .expect("unable to get component column");
+        assert_eq!(archetype.len(), len, "unexpected column length");
+        column.as_ptr()
+    }
+}
 
 impl<'w, T: Component> QueryParamFetch<'w> for QryRefMutFetch<'w, T> {
     type State = QryRefMutState<T>;
@@ -112,8 +271,148 @@ impl<'w, T: Component> QueryParamFetch<'w> for QryRefMutFetch<'w, T> {
 
     #[inline]
     fn fetch(res: &'w ResourcesSend, state: &QryRefMutState<T>) -> Self {
+        if <T::Storage as Storage>::DISJOINT && state.validated {
+            Self::Disjoint(
+                res.borrow_res_id(state.storage_id)
+                    .expect("unable to borrow component"),
+            )
+        } else {
+            Self::Exclusive(
+                res.borrow_res_mut_id(state.storage_id)
+                    .expect("unable to borrow mut component"),
+            )
+        }
+    }
+
+    #[inline(always)]
+    fn set_archetype(&mut self, _state: &Self::State, _archetype: &Archetype) {}
+
+    #[inline]
+    fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_> {
+        if <T::Storage as Storage>::TAG {
+            // SAFETY: the archetype contains the tag (`matches_archetype`)
+            return unsafe { tag_mut() };
+        }
+        match self {
+            Self::Exclusive(storage) => storage
+                .get_mut(archetype.entities[index], archetype.id, index)
+                .expect("unable to get component item"),
+            Self::Disjoint(storage) => {
+                let column = Self::disjoint_column(storage, archetype);
+                assert!(index < archetype.len(), "unable to get component item");
+                // SAFETY: the index is in bounds; the other parameters of the
+                // system never access the components of this archetype
+                // (validated with the partitions of the queries), and every
+                // entity is only returned once by the query.
+                unsafe { &mut *column.add(index) }
+            }
+        }
+    }
+
+    #[inline]
+    fn matches_entity(&self, archetype: &Archetype, index: usize) -> bool {
+        !<T::Storage as Storage>::SPARSE
+            || self
+                .storage()
+                .contains(archetype.entities[index], archetype.id, index)
+    }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        self.storage().sparse_len()
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        self.storage().collect_sparse_entities(entities)
+    }
+}
+
+impl<'w, T: Component> QueryParamChunkFetch<'w> for QryRefMutFetch<'w, T>
+where
+    T::Storage: ColumnStorage,
+{
+    type Chunk<'a> = &'a mut [T] where Self: 'a;
+
+    #[inline]
+    fn get_chunk<'a>(&'a mut self, archetype: &'a Archetype) -> Self::Chunk<'a> {
+        if <T::Storage as Storage>::TAG {
+            // SAFETY: the archetype contains the tag (`matches_archetype`)
+            return unsafe { tag_column_mut(archetype.len()) };
+        }
+        match self {
+            Self::Exclusive(storage) => storage.column_mut(archetype.id),
+            Self::Disjoint(storage) => {
+                let column = Self::disjoint_column(storage, archetype);
+                // SAFETY: see `get`
+                unsafe { std::slice::from_raw_parts_mut(column, archetype.len()) }
+            }
+        }
+    }
+}
+
+impl<T: Component> QueryParam for Pin<&'_ mut T> {
+    type State = QryPinMutState<T>;
+    type Fetch<'w> = QryPinMutFetch<'w, T>;
+}
+
+#[doc(hidden)]
+pub struct QryPinMutState<T: Component>(QryRefMutState<T>);
+
+unsafe impl<T: Component> QueryParamState for QryPinMutState<T> {
+    #[inline]
+    fn init(_res: &Resources, components: &Components) -> Self {
+        let component_id = components.expect_id::<T>();
+        let component = components.get(component_id).unwrap();
+        assert!(
+            <T::Storage as Storage>::PINNED,
+            "component {} is not pinned and can't be queried with `Pin<&mut>`",
+            component.name()
+        );
+        Self(QryRefMutState {
+            storage_id: component.storage_id.typed(),
+            component_id,
+            name: component.name().to_owned(),
+            validated: false,
+        })
+    }
+
+    #[inline]
+    fn is_registered(components: &Components) -> bool {
+        QryRefMutState::<T>::is_registered(components)
+    }
+
+    #[inline]
+    fn update_access(&self, access: &mut ResourceAccess) {
+        self.0.update_access(access)
+    }
+
+    #[inline]
+    fn matches_archetype(&self, archetype: &Archetype) -> bool {
+        self.0.matches_archetype(archetype)
+    }
+
+    #[inline]
+    fn update_partition(&self, partition: &mut AccessPartition) {
+        self.0.update_partition(partition)
+    }
+
+    fn type_name(&self) -> Cow<'static, str> {
+        format!("Pin<&mut {}>", self.0.name).into()
+    }
+}
+
+#[doc(hidden)]
+pub struct QryPinMutFetch<'w, T: Component>(ResMut<'w, T::Storage>);
+
+impl<'w, T: Component> QueryParamFetch<'w> for QryPinMutFetch<'w, T> {
+    type State = QryPinMutState<T>;
+    type Item<'a> = Pin<&'a mut T> where Self: 'a;
+
+    #[inline]
+    fn fetch(res: &'w ResourcesSend, state: &QryPinMutState<T>) -> Self {
         Self(
-            res.borrow_res_mut_id(state.storage_id)
+            res.borrow_res_mut_id(state.0.storage_id)
                 .expect("unable to borrow mut component"),
         )
     }
@@ -124,9 +423,27 @@ impl<'w, T: Component> QueryParamFetch<'w> for QryRefMutFetch<'w, T> {
     #[inline]
     fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_> {
         self.0
-            .get_mut(archetype.entities[index], archetype.id, index)
+            .get_pin_mut(archetype.entities[index], archetype.id, index)
             .expect("unable to get component item")
     }
+
+    #[inline]
+    fn matches_entity(&self, archetype: &Archetype, index: usize) -> bool {
+        !<T::Storage as Storage>::SPARSE
+            || self
+                .0
+                .contains(archetype.entities[index], archetype.id, index)
+    }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        self.0.sparse_len()
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        self.0.collect_sparse_entities(entities)
+    }
 }
 
 impl QueryParam for Entity {
@@ -134,6 +451,9 @@ impl QueryParam for Entity {
     type Fetch<'w> = QryEntityFetch;
 }
 
+// SAFETY: no access to components
+unsafe impl ReadOnlyQueryParam for Entity {}
+
 #[doc(hidden)]
 pub struct QryEntityFetch;
 
@@ -155,6 +475,15 @@ impl QueryParamFetch<'_> for QryEntityFetch {
     }
 }
 
+impl QueryParamChunkFetch<'_> for QryEntityFetch {
+    type Chunk<'a> = &'a [Entity];
+
+    #[inline]
+    fn get_chunk<'a>(&'a mut self, archetype: &'a Archetype) -> &'a [Entity] {
+        &archetype.entities
+    }
+}
+
 impl<Q> QueryParam for Option<Q>
 where
     Q: QueryParam,
@@ -163,19 +492,44 @@ where
     type Fetch<'w> = QryOptionFetch<Q::Fetch<'w>>;
 }
 
+// SAFETY: delegates to Q
+unsafe impl<Q> ReadOnlyQueryParam for Option<Q> where Q: ReadOnlyQueryParam {}
+
+/// The state is `None`, when a component of the inner query was not
+/// registered (the items are always `None` then).
 #[doc(hidden)]
 #[repr(transparent)]
-pub struct QryOptionState<S>(S);
+pub struct QryOptionState<S>(Option<S>);
 
 unsafe impl<S: QueryParamState> QueryParamState for QryOptionState<S> {
     #[inline]
     fn init(resources: &Resources, components: &Components) -> Self {
-        Self(S::init(resources, components))
+        if S::is_registered(components) {
+            Self(Some(S::init(resources, components)))
+        } else {
+            Self(None)
+        }
     }
 
     #[inline]
     fn update_access(&self, access: &mut ResourceAccess) {
-        self.0.update_access(access);
+        if let Some(state) = &self.0 {
+            state.update_access(access);
+        }
+    }
+
+    #[inline]
+    fn access_validated(&mut self) {
+        if let Some(state) = &mut self.0 {
+            state.access_validated();
+        }
+    }
+
+    fn type_name(&self) -> Cow<'static, str> {
+        match &self.0 {
+            Some(state) => format!("Option<{}>", state.type_name()).into(),
+            None => format!("Option<{}>", std::any::type_name::<S>()).into(),
+        }
     }
 
     #[inline]
@@ -187,7 +541,7 @@ unsafe impl<S: QueryParamState> QueryParamState for QryOptionState<S> {
 #[doc(hidden)]
 pub struct QryOptionFetch<F> {
     available: bool,
-    sub_fetch: F,
+    sub_fetch: Option<F>,
 }
 
 impl<'w, F> QueryParamFetch<'w> for QryOptionFetch<F>
@@ -201,22 +555,41 @@ where
     fn fetch(res: &'w ResourcesSend, state: &Self::State) -> Self {
         Self {
             available: false,
-            sub_fetch: F::fetch(res, &state.0),
+            sub_fetch: state.0.as_ref().map(|state| F::fetch(res, state)),
         }
     }
 
     #[inline]
     fn set_archetype(&mut self, state: &Self::State, archetype: &Archetype) {
-        self.available = state.0.matches_archetype(archetype);
-        self.sub_fetch.set_archetype(&state.0, archetype);
+        self.available = false;
+        if let (Some(state), Some(sub_fetch)) = (&state.0, &mut self.sub_fetch) {
+            self.available = state.matches_archetype(archetype);
+            sub_fetch.set_archetype(state, archetype);
+        }
     }
 
     #[inline]
     fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_> {
-        if self.available {
-            Some(self.sub_fetch.get(archetype, index))
-        } else {
-            None
+        match &mut self.sub_fetch {
+            Some(sub_fetch) if self.available && sub_fetch.matches_entity(archetype, index) => {
+                Some(sub_fetch.get(archetype, index))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'w, F> QueryParamChunkFetch<'w> for QryOptionFetch<F>
+where
+    F: QueryParamChunkFetch<'w>,
+{
+    type Chunk<'a> = Option<F::Chunk<'a>> where Self: 'a;
+
+    #[inline]
+    fn get_chunk<'a>(&'a mut self, archetype: &'a Archetype) -> Self::Chunk<'a> {
+        match &mut self.sub_fetch {
+            Some(sub_fetch) if self.available => Some(sub_fetch.get_chunk(archetype)),
+            _ => None,
         }
     }
 }
@@ -226,6 +599,9 @@ impl QueryParam for () {
     type Fetch<'w> = ();
 }
 
+// SAFETY: no access to components
+unsafe impl ReadOnlyQueryParam for () {}
+
 unsafe impl QueryParamState for () {
     #[inline]
     fn init(_res: &Resources, _components: &Components) -> Self {}
@@ -253,6 +629,22 @@ impl QueryParamFetch<'_> for () {
     fn get(&mut self, _archetype: &Archetype, _index: usize) {}
 }
 
+impl QueryParamChunkFetch<'_> for () {
+    type Chunk<'a> = ();
+
+    #[inline(always)]
+    fn get_chunk(&mut self, _archetype: &Archetype) {}
+}
+
+#[inline]
+fn min_sparse_len(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
 macro_rules! impl_query_param {
     ([]) => ();
     ([$(($name:ident,$index:tt)),+]) => (
@@ -265,6 +657,13 @@ macro_rules! impl_query_param {
             type Fetch<'w> = ($($name::Fetch<'w>,)+);
         }
 
+        // SAFETY: delegates to all members
+        unsafe impl<$($name),+> ReadOnlyQueryParam for ($($name,)+)
+        where
+            $($name: ReadOnlyQueryParam,)+
+        {
+        }
+
         unsafe impl<$($name),+> QueryParamState for ($($name,)+)
         where
             $($name: QueryParamState,)+
@@ -274,12 +673,21 @@ macro_rules! impl_query_param {
                 ($($name::init(res, components),)+)
             }
 
+            #[inline]
+            fn is_registered(components: &Components) -> bool {
+                $($name::is_registered(components))&&+
+            }
+
             #[inline]
             fn update_access(
                 &self,
                 access: &mut ResourceAccess,
             ) {
-                $(self.$index.update_access(access);)+
+                access.extend_checked(None, [$({
+                    let mut member_access = ResourceAccess::new();
+                    self.$index.update_access(&mut member_access);
+                    (self.$index.type_name(), member_access)
+                }),+]);
             }
 
             #[inline]
@@ -287,6 +695,25 @@ macro_rules! impl_query_param {
                 $(self.$index.matches_archetype(archetype))&&+
             }
 
+            #[inline]
+            fn update_partition(&self, partition: &mut AccessPartition) {
+                $(self.$index.update_partition(partition);)+
+            }
+
+            #[inline]
+            fn access_validated(&mut self) {
+                $(self.$index.access_validated();)+
+            }
+
+            fn type_name(&self) -> Cow<'static, str> {
+                let names = [$(self.$index.type_name()),+];
+                if names.len() == 1 {
+                    format!("({},)", names[0]).into()
+                } else {
+                    format!("({})", names.join(", ")).into()
+                }
+            }
+
         }
 
         impl<'w, $($name),+> QueryParamFetch<'w> for ($($name,)+)
@@ -310,8 +737,43 @@ macro_rules! impl_query_param {
             fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_> {
                 ($(self.$index.get(archetype, index),)+)
             }
+
+            #[inline]
+            fn matches_entity(&self, archetype: &Archetype, index: usize) -> bool {
+                $(self.$index.matches_entity(archetype, index))&&+
+            }
+
+            #[inline]
+            fn sparse_len(&self) -> Option<usize> {
+                let mut min = None;
+                $(min = min_sparse_len(min, self.$index.sparse_len());)+
+                min
+            }
+
+            #[inline]
+            fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+                let Some(min) = self.sparse_len() else {
+                    return;
+                };
+                $(
+                    if self.$index.sparse_len() == Some(min) {
+                        return self.$index.collect_sparse_entities(entities);
+                    }
+                )+
+            }
         }
 
+        impl<'w, $($name),+> QueryParamChunkFetch<'w> for ($($name,)+)
+        where
+            $($name: QueryParamChunkFetch<'w>,)+
+        {
+            type Chunk<'a> = ($($name::Chunk<'a>,)+) where Self: 'a;
+
+            #[inline(always)]
+            fn get_chunk<'a>(&'a mut self, archetype: &'a Archetype) -> Self::Chunk<'a> {
+                ($(self.$index.get_chunk(archetype),)+)
+            }
+        }
     )
 }
 
@@ -1,11 +1,15 @@
-use std::marker::PhantomData;
+use std::{borrow::Cow, marker::PhantomData};
 
-use pulz_schedule::resource::ResourceAccess;
+use pulz_schedule::resource::{AccessPartition, ResourceAccess};
 
 use crate::{
     archetype::Archetype,
     component::{Component, Components},
-    query::{QryRefState, QueryParam, QueryParamFetch, QueryParamState},
+    entity::Entity,
+    query::{
+        QryRefState, QueryParam, QueryParamChunkFetch, QueryParamFetch, QueryParamState,
+        ReadOnlyQueryParam,
+    },
     resource::{Resources, ResourcesSend},
 };
 
@@ -61,9 +65,19 @@ where
     type Fetch<'w> = QryWithoutFilterFetch<F, Q::Fetch<'w>>;
 }
 
+// SAFETY: filters don't access components, delegates to Q
+unsafe impl<F, Q> ReadOnlyQueryParam for Without<F, Q>
+where
+    F: Filter,
+    Q: ReadOnlyQueryParam,
+{
+}
+
+/// The filter is `None`, when a component of the filter was not registered
+/// (so it doesn't exclude any entity).
 #[doc(hidden)]
 pub struct QryWithoutFilterState<F, Q> {
-    filter: F,
+    filter: Option<F>,
     query: Q,
 }
 
@@ -72,12 +86,22 @@ unsafe impl<F: QueryParamState, Q: QueryParamState> QueryParamState
 {
     #[inline]
     fn init(resources: &Resources, components: &Components) -> Self {
+        let filter = if F::is_registered(components) {
+            Some(F::init(resources, components))
+        } else {
+            None
+        };
         Self {
-            filter: F::init(resources, components),
+            filter,
             query: Q::init(resources, components),
         }
     }
 
+    #[inline]
+    fn is_registered(components: &Components) -> bool {
+        Q::is_registered(components)
+    }
+
     #[inline]
     fn update_access(&self, access: &mut ResourceAccess) {
         // TODO: special handling for sparse filter components
@@ -87,7 +111,38 @@ unsafe impl<F: QueryParamState, Q: QueryParamState> QueryParamState
     #[inline]
     fn matches_archetype(&self, archetype: &Archetype) -> bool {
         // TODO: special handling for sparse filter components
-        !self.filter.matches_archetype(archetype) && self.query.matches_archetype(archetype)
+        !self
+            .filter
+            .as_ref()
+            .map_or(false, |filter| filter.matches_archetype(archetype))
+            && self.query.matches_archetype(archetype)
+    }
+
+    #[inline]
+    fn update_partition(&self, partition: &mut AccessPartition) {
+        // only a single excluded component can be expressed as a partition
+        let mut filter_partition = AccessPartition::new();
+        if let Some(filter) = &self.filter {
+            filter.update_partition(&mut filter_partition);
+        }
+        let mut required = filter_partition.with.iter();
+        if let (Some(component), None) = (required.next(), required.next()) {
+            partition.without.insert(component);
+        }
+        self.query.update_partition(partition);
+    }
+
+    #[inline]
+    fn access_validated(&mut self) {
+        self.query.access_validated();
+    }
+
+    fn type_name(&self) -> Cow<'static, str> {
+        let filter = match &self.filter {
+            Some(filter) => filter.type_name(),
+            None => Cow::Borrowed(std::any::type_name::<F>()),
+        };
+        format!("Without<{}, {}>", filter, self.query.type_name()).into()
     }
 }
 
@@ -122,6 +177,34 @@ where
     fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_> {
         self.query.get(archetype, index)
     }
+
+    #[inline(always)]
+    fn matches_entity(&self, archetype: &Archetype, index: usize) -> bool {
+        self.query.matches_entity(archetype, index)
+    }
+
+    #[inline(always)]
+    fn sparse_len(&self) -> Option<usize> {
+        self.query.sparse_len()
+    }
+
+    #[inline(always)]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        self.query.collect_sparse_entities(entities)
+    }
+}
+
+impl<'w, F, Q> QueryParamChunkFetch<'w> for QryWithoutFilterFetch<F, Q>
+where
+    F: Filter,
+    Q: QueryParamChunkFetch<'w>,
+{
+    type Chunk<'a> = Q::Chunk<'a> where Self: 'a;
+
+    #[inline(always)]
+    fn get_chunk<'a>(&'a mut self, archetype: &'a Archetype) -> Self::Chunk<'a> {
+        self.query.get_chunk(archetype)
+    }
 }
 
 pub struct With<F, Q>(PhantomData<fn(Q, F)>);
@@ -135,6 +218,14 @@ where
     type Fetch<'w> = QryWithFilterFetch<F, Q::Fetch<'w>>;
 }
 
+// SAFETY: filters don't access components, delegates to Q
+unsafe impl<F, Q> ReadOnlyQueryParam for With<F, Q>
+where
+    F: Filter,
+    Q: ReadOnlyQueryParam,
+{
+}
+
 #[doc(hidden)]
 pub struct QryWithFilterState<F, S> {
     filter: F,
@@ -150,6 +241,11 @@ unsafe impl<F: QueryParamState, S: QueryParamState> QueryParamState for QryWithF
         }
     }
 
+    #[inline]
+    fn is_registered(components: &Components) -> bool {
+        F::is_registered(components) && S::is_registered(components)
+    }
+
     #[inline]
     fn update_access(&self, access: &mut ResourceAccess) {
         // TODO: special handling for sparce filter components
@@ -160,6 +256,26 @@ unsafe impl<F: QueryParamState, S: QueryParamState> QueryParamState for QryWithF
     fn matches_archetype(&self, archetype: &Archetype) -> bool {
         self.filter.matches_archetype(archetype) && self.query.matches_archetype(archetype)
     }
+
+    #[inline]
+    fn update_partition(&self, partition: &mut AccessPartition) {
+        self.filter.update_partition(partition);
+        self.query.update_partition(partition);
+    }
+
+    #[inline]
+    fn access_validated(&mut self) {
+        self.query.access_validated();
+    }
+
+    fn type_name(&self) -> Cow<'static, str> {
+        format!(
+            "With<{}, {}>",
+            self.filter.type_name(),
+            self.query.type_name()
+        )
+        .into()
+    }
 }
 
 #[doc(hidden)]
@@ -193,4 +309,32 @@ where
     fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_> {
         self.query.get(archetype, index)
     }
+
+    #[inline(always)]
+    fn matches_entity(&self, archetype: &Archetype, index: usize) -> bool {
+        self.query.matches_entity(archetype, index)
+    }
+
+    #[inline(always)]
+    fn sparse_len(&self) -> Option<usize> {
+        self.query.sparse_len()
+    }
+
+    #[inline(always)]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        self.query.collect_sparse_entities(entities)
+    }
+}
+
+impl<'w, F, Q> QueryParamChunkFetch<'w> for QryWithFilterFetch<F, Q>
+where
+    F: Filter,
+    Q: QueryParamChunkFetch<'w>,
+{
+    type Chunk<'a> = Q::Chunk<'a> where Self: 'a;
+
+    #[inline(always)]
+    fn get_chunk<'a>(&'a mut self, archetype: &'a Archetype) -> Self::Chunk<'a> {
+        self.query.get_chunk(archetype)
+    }
 }
@@ -1,12 +1,18 @@
-use std::sync::{
-    atomic::{AtomicPtr, AtomicUsize, Ordering},
-    Mutex,
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError, RwLock,
+    },
 };
 
-pub use self::exec::Query;
+pub use pulz_ecs_macros::QueryData;
+
+pub use self::exec::{Query, QueryCursor};
 use crate::{
     archetype::{Archetype, ArchetypeId, ArchetypeSet},
     component::Components,
+    entity::Entity,
     WorldInner,
 };
 
@@ -20,16 +26,47 @@ pub trait QueryParam {
     type Fetch<'w>: QueryParamFetch<'w, State = Self::State>;
 }
 
+/// Marker for [`QueryParam`]s that only have shared access to components.
+///
+/// # Safety
+/// The fetch must not access any component exclusively.
+pub unsafe trait ReadOnlyQueryParam: QueryParam {}
+
 /// # Safety
 /// update_access should mark all used resources with ther usage.
 pub unsafe trait QueryParamState: Send + Sync + Sized + 'static {
     /// Looks up data that can be re-used between multiple query invocations
     fn init(resources: &Resources, components: &Components) -> Self;
 
+    /// Returns `false`, when a component of the query was never registered
+    /// ([`init`](Self::init) panics in this case).
+    #[inline]
+    fn is_registered(_components: &Components) -> bool {
+        true
+    }
+
     fn update_access(&self, access: &mut ResourceAccess);
 
     /// Checks if the archetype matches the query
     fn matches_archetype(&self, archetype: &Archetype) -> bool;
+
+    /// Collects the (offsets of) components, that every matching archetype
+    /// has (`with`) or doesn't have (`without`).
+    ///
+    /// This is used to detect queries that can never match the same entity.
+    #[inline]
+    fn update_partition(&self, _partition: &mut AccessPartition) {}
+
+    /// Called when the access of the query was validated against the other
+    /// parameters of its system, so partitioned storages can be borrowed
+    /// shared (see [`ResourceAccess::add_partitioned`]).
+    #[inline]
+    fn access_validated(&mut self) {}
+
+    /// readable name of the query (used for reporting access conflicts)
+    fn type_name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(std::any::type_name::<Self>())
+    }
 }
 
 pub trait QueryParamFetch<'w>: Send {
@@ -47,35 +84,88 @@ pub trait QueryParamFetch<'w>: Send {
 
     /// Access the given item in this archetype
     fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_>;
+
+    /// Checks if the given item in this archetype matches the query.
+    ///
+    /// Archetype components are already handled by
+    /// [`QueryParamState::matches_archetype`], so this only needs to be
+    /// implemented for sparse components.
+    #[inline(always)]
+    fn matches_entity(&self, _archetype: &Archetype, _index: usize) -> bool {
+        true
+    }
+
+    /// Returns the length of the smallest sparse set that is required by this
+    /// query, or `None` when no sparse components are required.
+    #[inline(always)]
+    fn sparse_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// Appends the entities of the smallest required sparse set (see
+    /// [`sparse_len`](Self::sparse_len)) to `entities`.
+    #[inline(always)]
+    fn collect_sparse_entities(&self, _entities: &mut Vec<Entity>) {}
+}
+
+/// A [`QueryParamFetch`] that can access all items of an archetype at once,
+/// as contiguous slices.
+///
+/// This is only implemented for components with a [`ColumnStorage`](crate::storage::ColumnStorage).
+pub trait QueryParamChunkFetch<'w>: QueryParamFetch<'w> {
+    /// Type of the chunk to be fetched
+    type Chunk<'a>
+    where
+        Self: 'a;
+
+    /// Access all items of this archetype
+    fn get_chunk<'a>(&'a mut self, archetype: &'a Archetype) -> Self::Chunk<'a>;
 }
 
 /// Type of values yielded by a query
 pub type QueryItem<'w, 'a, Q> = <<Q as QueryParam>::Fetch<'w> as QueryParamFetch<'w>>::Item<'a>;
 
+/// Type of chunks yielded by [`Query::for_each_chunk`]
+pub type QueryChunk<'w, 'a, Q> =
+    <<Q as QueryParam>::Fetch<'w> as QueryParamChunkFetch<'w>>::Chunk<'a>;
+
 pub mod exec;
 mod fetch;
 mod filter;
 pub use fetch::*;
 pub use filter::*;
 use pulz_schedule::resource::{
-    FromResourcesMut, ResourceAccess, ResourceId, Resources, ResourcesSend,
+    AccessPartition, ResourceAccess, ResourceId, Resources, ResourcesSend,
 };
 
-struct QueryState<S>
+/// The cached state of a query: the looked up components and the set of
+/// matching archetypes.
+///
+/// Systems own the state of their queries. Outside of systems, a state can be
+/// created once and used to run the same query multiple times.
+pub struct QueryState<Q>
 where
-    S: QueryParamState,
+    Q: QueryParam,
 {
     world_resource_id: ResourceId<WorldInner>,
-    param_state: S,
+    param_state: Q::State,
+    access: ResourceAccess,
+    /// the access was validated against the other parameters of a system
+    validated: bool,
 
     last_archetype_index: AtomicUsize,
-    updating_archetypes: Mutex<()>,
-    matching_archetypes_p: AtomicPtr<ArchetypeSet>,
+    matching_archetypes: RwLock<Arc<ArchetypeSet>>,
+    /// reused buffer for the entities of the sparse set, that drives the
+    /// iteration
+    sparse_entities: Mutex<Vec<Entity>>,
+    /// the (sorted) entities of the source queries of a lens, when they
+    /// require sparse components, that are not tracked by the archetypes
+    lens_entities: Option<Vec<Entity>>,
 }
 
-impl<S> QueryState<S>
+impl<Q> QueryState<Q>
 where
-    S: QueryParamState,
+    Q: QueryParam,
 {
     pub fn new(resources: &mut Resources) -> Self {
         let world_id = resources.init::<WorldInner>();
@@ -88,80 +178,110 @@ where
         world: &WorldInner,
         resource_id: ResourceId<WorldInner>,
     ) -> Self {
-        let state = S::init(resources, &world.components);
-        // TODO: detect if only sparse components are used, and handle this seperately
+        let state = Q::State::init(resources, &world.components);
+
+        let mut access = ResourceAccess::new();
+        state.update_access(&mut access);
+        if let Some(conflict) = access.conflicts().first() {
+            panic!(
+                "query `{}` has conflicting parameters: {conflict}",
+                state.type_name()
+            );
+        }
 
         let query = Self {
             world_resource_id: resource_id,
             param_state: state,
+            access,
+            validated: false,
             last_archetype_index: AtomicUsize::new(0),
-            updating_archetypes: Mutex::new(()),
-            matching_archetypes_p: AtomicPtr::new(std::ptr::null_mut()),
+            matching_archetypes: RwLock::new(Arc::new(ArchetypeSet::new())),
+            sparse_entities: Mutex::new(Vec::new()),
+            lens_entities: None,
         };
         query.update_archetypes(world);
         query
     }
 
-    fn update_archetypes(&self, world: &WorldInner) {
-        let archetypes = &world.archetypes;
-        let last_archetype_index = archetypes.len();
-        let old_archetype_index = self.last_archetype_index.load(Ordering::Relaxed);
-        if old_archetype_index >= last_archetype_index {
-            // no new archetypes
-            return;
-        }
-        let lock = self.updating_archetypes.lock();
-
-        let mut archetypes_scratch: Option<Box<ArchetypeSet>> = None;
-
-        for index in old_archetype_index..last_archetype_index {
-            let id = ArchetypeId::new(index);
-            let archetype = &archetypes[id];
-            if self.param_state.matches_archetype(archetype) {
-                // init scratch
-                let scratch = archetypes_scratch.get_or_insert_with(|| {
-                    let ptr = self.matching_archetypes_p.load(Ordering::Relaxed);
-                    if ptr.is_null() {
-                        Default::default()
-                    } else {
-                        unsafe { Box::new((*ptr).clone()) }
-                    }
-                });
-                // indert archetype
-                scratch.insert(id);
-            }
-        }
+    /// Runs the query with this state.
+    ///
+    /// # Panics
+    /// Panics when a component of the query is already borrowed in a
+    /// conflicting way (for example by an other query).
+    #[inline]
+    pub fn query<'w>(&'w self, resources: &'w Resources) -> Query<'w, Q>
+    where
+        Q: 'w,
+    {
+        Query::from_state(resources, self)
+    }
 
-        if let Some(new) = archetypes_scratch {
-            // replace
-            let old = self
-                .matching_archetypes_p
-                .swap(Box::into_raw(new), Ordering::Relaxed);
-            if !old.is_null() {
-                unsafe { drop(Box::from_raw(old)) }
-            }
-        }
+    fn access_validated(&mut self) {
+        self.validated = true;
+        self.param_state.access_validated();
+    }
 
-        self.last_archetype_index
-            .store(last_archetype_index, Ordering::Relaxed);
+    /// Checks if the entity at `index` of the archetype matches the query
+    /// (`fetch` must be set to the archetype).
+    #[inline]
+    fn matches_entity<'w>(
+        &self,
+        fetch: &Q::Fetch<'w>,
+        archetype: &Archetype,
+        index: usize,
+    ) -> bool {
+        fetch.matches_entity(archetype, index)
+            && self.lens_entities.as_ref().map_or(true, |entities| {
+                entities.binary_search(&archetype.entities[index]).is_ok()
+            })
+    }
 
-        drop(lock);
+    /// Removes all archetypes that are not in `archetypes` from the set of
+    /// matching archetypes.
+    fn restrict_archetypes(&mut self, archetypes: &ArchetypeSet) {
+        let matching_archetypes = self
+            .matching_archetypes
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        Arc::make_mut(matching_archetypes).retain_set(archetypes);
     }
 
-    fn matching_archetypes(&self) -> &ArchetypeSet {
-        static EMPTY: ArchetypeSet = ArchetypeSet::new();
-        let ptr = self.matching_archetypes_p.load(Ordering::Relaxed);
-        if ptr.is_null() {
-            &EMPTY
-        } else {
-            unsafe { &*ptr }
+    /// Adds the archetypes, that were created since the last update, to the
+    /// set of matching archetypes, and returns a snapshot of the set.
+    ///
+    /// The set is replaced copy-on-write, so snapshots that are still used
+    /// by other queries are never modified or freed.
+    fn update_archetypes(&self, world: &WorldInner) -> Arc<ArchetypeSet> {
+        let archetypes = &world.archetypes;
+        let last_archetype_index = archetypes.len();
+        if self.last_archetype_index.load(Ordering::Acquire) >= last_archetype_index {
+            // no new archetypes
+            return self
+                .matching_archetypes
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
         }
-    }
-}
 
-impl<S: QueryParamState> FromResourcesMut for QueryState<S> {
-    fn from_resources_mut(resources: &mut Resources) -> Self {
-        Self::new(resources)
+        let mut matching_archetypes = self
+            .matching_archetypes
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        // re-check: the set may have been updated while waiting for the lock
+        let old_archetype_index = self.last_archetype_index.load(Ordering::Acquire);
+        if old_archetype_index < last_archetype_index {
+            for index in old_archetype_index..last_archetype_index {
+                let id = ArchetypeId::new(index);
+                let archetype = &archetypes[id];
+                if self.param_state.matches_archetype(archetype) {
+                    // clones the set, when it is still used by a query
+                    Arc::make_mut(&mut matching_archetypes).insert(id);
+                }
+            }
+            self.last_archetype_index
+                .store(last_archetype_index, Ordering::Release);
+        }
+        matching_archetypes.clone()
     }
 }
 
@@ -172,7 +292,13 @@ mod test {
 
     use pulz_schedule::resource::Resources;
 
-    use crate::{component::Component, prelude::Query, WorldExt};
+    use crate::{
+        component::Component,
+        entity::{Entity, UniqueEntities},
+        prelude::Query,
+        query::{QueryCursor, QueryData, QueryState, With, Without},
+        WorldExt, WorldInner,
+    };
 
     #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
     struct A(usize);
@@ -257,6 +383,429 @@ mod test {
         assert_eq!(374500, sum4);
     }
 
+    #[test]
+    fn test_query_chunks() {
+        let mut resources = Resources::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..1000 {
+                match i % 4 {
+                    1 => world.spawn().insert(A(i)),
+                    2 => world.spawn().insert(B(i)),
+                    _ => world.spawn().insert(A(i)).insert(B(i)),
+                };
+            }
+        }
+
+        let mut q1 = Query::<(Entity, &mut A, &B)>::new(&mut resources);
+        let mut chunks = 0;
+        let mut counter1 = 0;
+        q1.for_each_chunk(|(entities, a, b)| {
+            assert_eq!(entities.len(), a.len());
+            assert_eq!(entities.len(), b.len());
+            for (a, b) in a.iter_mut().zip(b) {
+                a.0 += b.0;
+            }
+            chunks += 1;
+            counter1 += entities.len();
+        });
+        assert_eq!(1, chunks);
+        assert_eq!(500, counter1);
+        drop(q1);
+
+        let mut q2 = Query::<(&A, Option<&B>)>::new(&mut resources);
+        let mut counter2 = 0;
+        let mut sum2 = 0;
+        q2.for_each_chunk(|(a, b)| {
+            counter2 += a.len();
+            sum2 += a.iter().map(|a| a.0).sum::<usize>();
+            if let Some(b) = b {
+                assert_eq!(a.len(), b.len());
+            }
+        });
+        assert_eq!(750, counter2);
+        assert_eq!(624250, sum2);
+    }
+
+    #[test]
+    fn test_query_many() {
+        let mut resources = Resources::new();
+        let mut entities = Vec::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..10 {
+                let entity = match i % 4 {
+                    1 => world.spawn().insert(A(i)).id(),
+                    2 => world.spawn().insert(B(i)).id(),
+                    _ => world.spawn().insert(A(i)).insert(B(i)).id(),
+                };
+                entities.push(entity);
+            }
+        }
+
+        let mut q1 = Query::<&A>::new(&mut resources);
+        let list = [entities[3], entities[2], entities[1], entities[3]];
+        let mut iter = q1.iter_many(list);
+        let mut result = Vec::new();
+        while let Some(a) = iter.fetch_next() {
+            result.push(*a);
+        }
+        assert_eq!(vec![A(3), A(1), A(3)], result);
+        assert_eq!(&[entities[2]], iter.mismatched());
+        drop(q1);
+
+        assert_eq!(
+            Err(entities[3]),
+            UniqueEntities::from_entities([entities[3], entities[4], entities[3]])
+        );
+        let unique: UniqueEntities = [entities[4], entities[5], entities[4], entities[6]]
+            .into_iter()
+            .collect();
+        assert_eq!(&[entities[4], entities[5], entities[6]], unique.as_slice());
+
+        let mut q2 = Query::<&mut A>::new(&mut resources);
+        let mut iter = q2.iter_many_mut(&unique);
+        let mut refs: Vec<&mut A> = iter.by_ref().collect();
+        assert_eq!(2, refs.len());
+        for a in &mut refs {
+            a.0 += 100;
+        }
+        assert_eq!(&[entities[6]], iter.mismatched());
+        assert_eq!(Some(A(104)), q2.get(entities[4]).copied());
+        assert_eq!(Some(A(105)), q2.get(entities[5]).copied());
+    }
+
+    #[test]
+    fn test_query_world() {
+        let mut resources = Resources::new();
+        let mut entities = Vec::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..1000 {
+                let entity = match i % 4 {
+                    1 => world.spawn().insert(A(i)).id(),
+                    2 => world.spawn().insert(B(i)).id(),
+                    _ => world.spawn().insert(A(i)).insert(B(i)).id(),
+                };
+                entities.push(entity);
+            }
+        }
+
+        let world = resources.world();
+        let mut q1 = world.query::<(&A, Option<&B>)>();
+        let mut counter1 = 0;
+        let mut sum1 = 0;
+        for (a, b) in q1.iter() {
+            counter1 += 1;
+            sum1 += a.0 + b.map_or(0, |b| b.0);
+        }
+        assert_eq!(750, counter1);
+        assert_eq!(374500 + 249750, sum1);
+
+        let entity = world.entity(entities[3]).unwrap();
+        let mut components = entity.get_components::<(Entity, &A, &B)>().unwrap();
+        assert_eq!((entities[3], &A(3), &B(3)), components.get());
+        let entity = world.entity(entities[2]).unwrap();
+        assert!(entity.get_components::<(&A, &B)>().is_none());
+        // `D` was never registered
+        let entity = world.entity(entities[3]).unwrap();
+        let mut components = entity.get_components::<(&A, Option<&D>)>().unwrap();
+        assert_eq!((&A(3), None), components.get());
+        let mut components = entity.get_components::<Without<&D, &A>>().unwrap();
+        assert_eq!(&A(3), components.get());
+        assert!(entity.get_components::<With<&D, &A>>().is_none());
+    }
+
+    #[test]
+    fn test_query_unregistered_filter() {
+        let mut resources = Resources::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..10 {
+                world.spawn().insert(A(i));
+            }
+        }
+        // `D` was never registered, so it excludes no entity
+        let world = resources.world();
+        let mut q = world.query::<Without<&D, &A>>();
+        assert_eq!(10, q.iter().count());
+        let mut q = world.query::<(&A, Option<&D>)>();
+        assert!(q.iter().all(|(_, d)| d.is_none()));
+    }
+
+    #[test]
+    fn test_query_sparse_buffer() {
+        let mut resources = Resources::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..100 {
+                let mut entity = world.spawn();
+                entity.insert(A(i));
+                if i % 10 == 0 {
+                    entity.insert(C(i));
+                }
+            }
+        }
+
+        let state = QueryState::<(&A, &C)>::new(&mut resources);
+        assert_eq!(10, state.query(&resources).iter().count());
+        // the buffer of the sparse entities is handed back to the state
+        let capacity = state.sparse_entities.lock().unwrap().capacity();
+        assert!(capacity >= 10);
+        assert_eq!(10, state.query(&resources).into_iter().count());
+        assert_eq!(capacity, state.sparse_entities.lock().unwrap().capacity());
+    }
+
+    #[test]
+    fn test_query_sparse() {
+        let mut resources = Resources::new();
+        let mut entities = Vec::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..1000 {
+                let mut entity = world.spawn();
+                entity.insert(A(i));
+                if i % 2 == 0 {
+                    entity.insert(B(i));
+                }
+                if i % 10 == 0 {
+                    entity.insert(C(i));
+                }
+                entities.push(entity.id());
+            }
+        }
+
+        let mut q1 = Query::<&C>::new(&mut resources);
+        assert_eq!(Some(C(10)), q1.get(entities[10]).copied());
+        assert_eq!(None, q1.get(entities[11]).copied());
+        let mut counter1 = 0;
+        let mut sum1 = 0;
+        for c in q1.iter() {
+            counter1 += 1;
+            sum1 += c.0;
+        }
+        assert_eq!(100, counter1);
+        assert_eq!(49500, sum1);
+        drop(q1);
+
+        let mut q2 = Query::<(&mut C, &B)>::new(&mut resources);
+        let mut counter2 = 0;
+        for (c, b) in q2.iter() {
+            assert_eq!(c.0, b.0);
+            c.0 += 1;
+            counter2 += 1;
+        }
+        assert_eq!(100, counter2);
+        drop(q2);
+
+        let mut q3 = Query::<(&A, Option<&C>)>::new(&mut resources);
+        let mut counter3 = 0;
+        let mut sum3 = 0;
+        for (_a, c) in q3.iter() {
+            counter3 += 1;
+            sum3 += c.map_or(0, |c| c.0);
+        }
+        assert_eq!(1000, counter3);
+        assert_eq!(49600, sum3);
+    }
+
+    #[derive(QueryData)]
+    struct AData<'w> {
+        entity: Entity,
+        a: &'w mut A,
+        c: Option<&'w C>,
+    }
+
+    #[derive(QueryData)]
+    #[query(filter = "&B", without = "&D")]
+    struct NestedData<'w> {
+        data: AData<'w>,
+        b: &'w B,
+    }
+
+    #[test]
+    fn test_query_derive() {
+        let mut resources = Resources::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..100 {
+                let mut entity = world.spawn();
+                entity.insert(A(i)).insert(B(i));
+                if i % 10 == 0 {
+                    entity.insert(C(i));
+                }
+                if i % 4 == 0 {
+                    entity.insert(D(i));
+                }
+            }
+            world.spawn().insert(A(1000));
+        }
+
+        let mut q1 = Query::<AData<'_>>::new(&mut resources);
+        let mut entities = Vec::new();
+        let mut sum1 = 0;
+        for AData { entity, a, c } in q1.iter() {
+            assert!(c.map_or(true, |c| c.0 == a.0));
+            entities.push(entity);
+            sum1 += a.0 + c.map_or(0, |c| c.0);
+            a.0 += 1;
+        }
+        assert_eq!(101, entities.len());
+        assert_eq!(4950 + 1000 + 450, sum1);
+        let data = q1.get(entities[10]).unwrap();
+        assert_eq!(entities[10], data.entity);
+        drop(q1);
+
+        let mut q2 = Query::<NestedData<'_>>::new(&mut resources);
+        let mut counter2 = 0;
+        for NestedData { data, b } in q2.iter() {
+            assert_eq!(data.a.0, b.0 + 1);
+            assert_ne!(0, b.0 % 4);
+            counter2 += 1;
+        }
+        assert_eq!(75, counter2);
+    }
+
+    #[test]
+    fn test_query_cursor() {
+        let mut resources = Resources::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..100 {
+                let mut entity = world.spawn();
+                entity.insert(A(i));
+                if i % 2 == 0 {
+                    entity.insert(B(i));
+                }
+            }
+        }
+
+        let mut cursor = QueryCursor::new();
+        let mut seen = Vec::new();
+        for _ in 0..3 {
+            let mut q = Query::<&A>::new(&mut resources);
+            seen.extend(q.iter_from(&mut cursor).take(30).map(|a| a.0));
+        }
+        assert_eq!(90, seen.len());
+        assert!((cursor.progress() - 0.9).abs() < 1e-6);
+
+        // new archetypes are visited before wrapping around
+        resources.world_mut().spawn().insert(A(100)).insert(D(100));
+
+        let mut q = Query::<&A>::new(&mut resources);
+        seen.extend(q.iter_from(&mut cursor).take(30).map(|a| a.0));
+        drop(q);
+        assert_eq!(120, seen.len());
+        let mut first_cycle = seen[..101].to_vec();
+        first_cycle.sort_unstable();
+        assert_eq!((0..101).collect::<Vec<_>>(), first_cycle);
+        assert_eq!(&seen[..19], &seen[101..]);
+
+        // a single call visits every entity at most once
+        let mut q = Query::<&A>::new(&mut resources);
+        let rest: Vec<_> = q.iter_from(&mut cursor).map(|a| a.0).collect();
+        assert_eq!(101, rest.len());
+        assert_eq!(&seen[19..101], &rest[..82]);
+        assert_eq!(&seen[..19], &rest[82..]);
+    }
+
+    #[test]
+    fn test_query_lens() {
+        let mut resources = Resources::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..1000 {
+                match i % 4 {
+                    1 => world.spawn().insert(A(i)),
+                    2 => world.spawn().insert(B(i)),
+                    _ => world.spawn().insert(A(i)).insert(B(i)),
+                };
+            }
+        }
+
+        fn sum_a(mut query: Query<'_, &A>) -> usize {
+            query.iter().map(|a| a.0).sum()
+        }
+
+        let mut q1 = Query::<(&mut A, &B)>::new(&mut resources);
+        assert_eq!(249750, sum_a(q1.transmute_lens::<&A>().query()));
+        for a in q1.transmute_lens::<&mut A>().query() {
+            a.0 += 1;
+        }
+        assert_eq!(250250, sum_a(q1.transmute_lens().query()));
+        drop(q1);
+
+        let state2 = QueryState::<&A>::new(&mut resources);
+        let state3 = QueryState::<(Entity, &B)>::new(&mut resources);
+        let mut q2 = state2.query(&resources);
+        let mut q3 = state3.query(&resources);
+        let mut lens = q2.join::<_, (Entity, &A, &B)>(&mut q3);
+        let mut counter = 0;
+        for (_, a, b) in lens.query() {
+            assert_eq!(a.0, b.0 + 1);
+            counter += 1;
+        }
+        assert_eq!(500, counter);
+    }
+
+    #[test]
+    fn test_query_lens_sparse() {
+        let mut resources = Resources::new();
+        let mut entities = Vec::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..1000 {
+                let mut entity = world.spawn();
+                entity.insert(A(i));
+                if i % 2 == 0 {
+                    entity.insert(B(i));
+                }
+                if i % 10 == 0 {
+                    entity.insert(C(i));
+                }
+                entities.push(entity.id());
+            }
+        }
+
+        let mut q1 = Query::<(&A, &C)>::new(&mut resources);
+        let mut lens = q1.transmute_lens::<&A>();
+        let mut q = lens.query();
+        assert_eq!(Some(A(10)), q.get(entities[10]).copied());
+        assert_eq!(None, q.get(entities[11]).copied());
+        assert_eq!(100, q.iter().count());
+        assert_eq!(49500, q.iter().map(|a| a.0).sum::<usize>());
+        drop(q);
+        // the restriction is kept for nested lenses
+        assert_eq!(
+            100,
+            lens.query().transmute_lens::<&A>().query().iter().count()
+        );
+        drop(lens);
+        drop(q1);
+
+        let state2 = QueryState::<(&A, &C)>::new(&mut resources);
+        let state3 = QueryState::<&B>::new(&mut resources);
+        let mut q2 = state2.query(&resources);
+        let mut q3 = state3.query(&resources);
+        let mut lens = q3.join::<_, (&A, &B)>(&mut q2);
+        let mut counter = 0;
+        for (a, b) in lens.query() {
+            assert_eq!(0, a.0 % 10);
+            assert_eq!(a.0, b.0);
+            counter += 1;
+        }
+        assert_eq!(100, counter);
+    }
+
+    #[test]
+    #[should_panic(expected = "requires access that is not granted by `&")]
+    fn test_query_lens_exclusive_from_shared() {
+        let mut resources = Resources::new();
+        resources.world_mut().spawn().insert(A(1));
+        let mut q = Query::<&A>::new(&mut resources);
+        let _lens = q.transmute_lens::<&mut A>();
+    }
+
     #[test]
     fn test_query_sys() {
         let mut resources = Resources::new();
@@ -322,4 +871,166 @@ mod test {
         assert_eq!(750, counter3);
         assert_eq!(374750, sum3);
     }
+
+    #[test]
+    #[should_panic(expected = "is accessed by `&mut ")]
+    fn test_query_conflicting_access() {
+        let mut resources = Resources::new();
+        resources.world_mut().spawn().insert(A(1));
+        let _q = Query::<(&mut A, &A)>::new(&mut resources);
+    }
+
+    #[test]
+    #[should_panic(expected = "is accessed by `Query<&mut ")]
+    fn test_query_sys_conflicting_params() {
+        let mut resources = Resources::new();
+        resources.world_mut().spawn().insert(A(1)).insert(B(1));
+        resources.run(|_q1: Query<'_, &mut A>, _q2: Query<'_, (&A, &B)>| {});
+    }
+
+    #[test]
+    fn test_query_sys_disjoint_params() {
+        let mut resources = Resources::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..100 {
+                match i % 2 {
+                    0 => world.spawn().insert(A(i)),
+                    _ => world.spawn().insert(A(i)).insert(B(i)),
+                };
+            }
+        }
+
+        resources.run(
+            |mut q1: Query<'_, Without<&B, &mut A>>, mut q2: Query<'_, (&A, &B)>| {
+                let sum: usize = q2.iter().map(|(a, _)| a.0).sum();
+                for a in q1.iter() {
+                    a.0 += sum;
+                }
+            },
+        );
+
+        let mut q = Query::<Without<&B, &A>>::new(&mut resources);
+        let mut counter = 0;
+        let mut sum = 0;
+        for a in q.iter() {
+            counter += 1;
+            sum += a.0;
+        }
+        assert_eq!(50, counter);
+        assert_eq!(2450 + 50 * 2500, sum);
+    }
+
+    #[test]
+    fn test_query_state() {
+        let mut resources = Resources::new();
+        {
+            let mut world = resources.world_mut();
+            for i in 0..100 {
+                world.spawn().insert(A(i));
+            }
+        }
+
+        let state = QueryState::<&mut A>::new(&mut resources);
+        for a in state.query(&resources) {
+            a.0 += 1;
+        }
+
+        resources.world_mut().spawn().insert(A(1000)).insert(B(1));
+
+        let mut query = state.query(&resources);
+        assert_eq!(101, query.iter().count());
+        assert_eq!(5050 + 1000, query.iter().map(|a| a.0).sum::<usize>());
+    }
+
+    #[test]
+    #[should_panic(expected = "already mutably borrowed")]
+    fn test_query_state_conflicting_borrow() {
+        let mut resources = Resources::new();
+        resources.world_mut().spawn().insert(A(1));
+
+        let state = QueryState::<&mut A>::new(&mut resources);
+        let _q1 = state.query(&resources);
+        let _q2 = state.query(&resources);
+    }
+
+    struct Marker<const N: usize>;
+
+    impl<const N: usize> Component for Marker<N> {
+        type Storage = crate::storage::ArchetypeStorage<Self>;
+    }
+
+    /// spawns an entity in the archetype `(A, Marker<..>...)` selected by the bits of `index`
+    fn spawn_in_new_archetype(resources: &mut Resources, index: usize) {
+        let mut world = resources.world_mut();
+        let mut entity = world.spawn();
+        entity.insert(A(index));
+        macro_rules! insert_markers {
+            ($($bit:literal)*) => {$(
+                if index & (1 << $bit) != 0 {
+                    entity.insert(Marker::<$bit>);
+                }
+            )*};
+        }
+        insert_markers!(0 1 2 3 4 5 6);
+    }
+
+    #[test]
+    fn test_query_state_concurrent_update() {
+        let mut resources = Resources::new();
+        spawn_in_new_archetype(&mut resources, 0);
+        let state = QueryState::<&A>::new(&mut resources);
+
+        for index in 1..128 {
+            spawn_in_new_archetype(&mut resources, index);
+            let world = resources.borrow_res::<WorldInner>().unwrap();
+            let world: &WorldInner = &world;
+            std::thread::scope(|scope| {
+                for _ in 0..8 {
+                    scope.spawn(|| {
+                        let matching = state.update_archetypes(world);
+                        assert_eq!(index + 1, matching.iter().count());
+                    });
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_query_sys_parallel_with_new_archetypes() {
+        use crate::{schedule::Schedule, system::system_fn::ExclusiveResources};
+
+        const SYSTEMS: usize = 16;
+        const RUNS: usize = 128;
+
+        let mut resources = Resources::new();
+        spawn_in_new_archetype(&mut resources, 0);
+
+        let counts = Arc::new(Mutex::new(Vec::new()));
+        let mut schedule = Schedule::new();
+        let mut index = 0;
+        schedule.add_system(move |mut res: ExclusiveResources<'_>| {
+            index += 1;
+            spawn_in_new_archetype(&mut res, index);
+        });
+        for _ in 0..SYSTEMS {
+            let counts = counts.clone();
+            schedule.add_system(move |mut q: Query<'_, (Entity, &A)>| {
+                let count = q.iter().count();
+                counts.lock().unwrap().push(count);
+            });
+        }
+
+        for _ in 0..RUNS {
+            schedule.run(&mut resources);
+        }
+
+        // every run sees one more entity (in a new archetype) than the previous run
+        let mut counts = counts.lock().unwrap().clone();
+        counts.sort_unstable();
+        assert_eq!(SYSTEMS * RUNS, counts.len());
+        for (run, chunk) in counts.chunks(SYSTEMS).enumerate() {
+            assert!(chunk.iter().all(|&count| count == counts[0] + run));
+        }
+    }
 }
@@ -0,0 +1,233 @@
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Arc, Mutex, PoisonError, Weak,
+};
+
+use crate::{
+    entity::{Entity, EntityMut},
+    label::CoreSystemPhase,
+    resource::Resources,
+    schedule::Schedule,
+    system::system_fn::ExclusiveResources,
+    world::{World, WorldMut},
+    WorldExt,
+};
+
+/// A reference-counted handle to an entity.
+///
+/// The entity is despawned, after the last `StrongEntity` handle of it has
+/// been dropped (see [`WorldMut::spawn_ref_counted`]).
+#[derive(Clone)]
+pub struct StrongEntity(Arc<EntityHandle>);
+
+/// A weak handle to a reference-counted entity, that doesn't keep the entity
+/// alive.
+#[derive(Clone)]
+pub struct WeakEntity {
+    entity: Entity,
+    handle: Weak<EntityHandle>,
+}
+
+struct EntityHandle {
+    entity: Entity,
+    dropped: Sender<Entity>,
+}
+
+impl Drop for EntityHandle {
+    fn drop(&mut self) {
+        // the world may already be gone
+        let _ = self.dropped.send(self.entity);
+    }
+}
+
+impl StrongEntity {
+    /// Returns the id of the entity.
+    #[inline]
+    pub fn id(&self) -> Entity {
+        self.0.entity
+    }
+
+    #[inline]
+    pub fn downgrade(&self) -> WeakEntity {
+        WeakEntity {
+            entity: self.0.entity,
+            handle: Arc::downgrade(&self.0),
+        }
+    }
+}
+
+impl std::fmt::Debug for StrongEntity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("StrongEntity").field(&self.0.entity).finish()
+    }
+}
+
+impl WeakEntity {
+    /// Returns the id of the entity.
+    #[inline]
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
+
+    /// Returns a strong handle of the entity, or `None` when all strong
+    /// handles have been dropped or the entity has been despawned.
+    pub fn upgrade(&self, world: &World<'_>) -> Option<StrongEntity> {
+        let handle = self.handle.upgrade()?;
+        if world.entities().contains(self.entity) {
+            Some(StrongEntity(handle))
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Debug for WeakEntity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("WeakEntity").field(&self.entity).finish()
+    }
+}
+
+/// Receives the entities of dropped [`StrongEntity`] handles.
+struct DroppedEntities {
+    sender: Sender<Entity>,
+    receiver: Mutex<Receiver<Entity>>,
+    /// `true`, when the system that despawns the entities was added to the
+    /// schedule
+    installed: bool,
+}
+
+impl Default for DroppedEntities {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+            installed: false,
+        }
+    }
+}
+
+impl DroppedEntities {
+    fn install_into(resources: &mut Resources) {
+        let id = resources.init::<Self>();
+        if resources.get_mut_id(id).unwrap().installed {
+            return;
+        }
+        // The schedule is taken out of the resources while it is running, so
+        // the system is added by a later call in this case.
+        let Some(mut schedule) = resources.borrow_res_mut::<Schedule>() else {
+            return;
+        };
+        schedule
+            .add_system(despawn_dropped)
+            .into_phase(CoreSystemPhase::Last);
+        drop(schedule);
+        resources.get_mut_id(id).unwrap().installed = true;
+    }
+}
+
+fn despawn_dropped(mut resources: ExclusiveResources<'_>) {
+    resources.world_mut().despawn_dropped();
+}
+
+impl WorldMut<'_> {
+    /// Spawns a new empty entity, that is despawned automatically, after the
+    /// last [`StrongEntity`] handle has been dropped.
+    ///
+    /// The entities are despawned by a system in
+    /// [`CoreSystemPhase::Last`], or by calling
+    /// [`despawn_dropped`](Self::despawn_dropped). The system is added to the
+    /// schedule by the first call, while the schedule is not running.
+    #[must_use]
+    pub fn spawn_ref_counted(&mut self) -> (StrongEntity, EntityMut<'_>) {
+        DroppedEntities::install_into(self.res);
+        let dropped = self
+            .res
+            .get_mut::<DroppedEntities>()
+            .unwrap()
+            .sender
+            .clone();
+        let entity = self.spawn();
+        let handle = StrongEntity(Arc::new(EntityHandle {
+            entity: entity.id(),
+            dropped,
+        }));
+        (handle, entity)
+    }
+
+    /// Despawns the entities of all dropped [`StrongEntity`] handles.
+    pub fn despawn_dropped(&mut self) {
+        let Some(dropped) = self.res.get_mut::<DroppedEntities>() else {
+            return;
+        };
+        let receiver = dropped
+            .receiver
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        let entities: Vec<Entity> = receiver.try_iter().collect();
+        for entity in entities {
+            self.despawn(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pulz_schedule::{resource::Resources, schedule::Schedule};
+
+    use crate::{component::Component, WorldExt};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    struct A(usize);
+
+    fn run_schedule(resources: &mut Resources) {
+        let mut schedule = resources.remove::<Schedule>().unwrap();
+        schedule.run(resources);
+        resources.insert_again(schedule);
+    }
+
+    #[test]
+    fn test_ref_counted_entity() {
+        let mut resources = Resources::new();
+        let (strong, weak) = {
+            let mut world = resources.world_mut();
+            let (strong, mut entity) = world.spawn_ref_counted();
+            entity.insert(A(1));
+            drop(entity);
+            let weak = strong.downgrade();
+            (strong, weak)
+        };
+        let id = strong.id();
+
+        let strong2 = strong.clone();
+        drop(strong);
+        run_schedule(&mut resources);
+        assert!(resources.world().entities().contains(id));
+        assert_eq!(Some(id), weak.upgrade(&resources.world()).map(|s| s.id()));
+
+        std::thread::spawn(move || drop(strong2)).join().unwrap();
+        assert!(weak.upgrade(&resources.world()).is_none());
+        assert!(resources.world().entities().contains(id));
+        run_schedule(&mut resources);
+        assert!(!resources.world().entities().contains(id));
+    }
+
+    #[test]
+    fn test_ref_counted_while_schedule_is_running() {
+        let mut resources = Resources::new();
+        // like spawning from a system, while the schedule is taken out
+        let schedule = resources.remove::<Schedule>().unwrap();
+        let (strong, _) = resources.world_mut().spawn_ref_counted();
+        let id = strong.id();
+        drop(strong);
+        resources.insert_again(schedule);
+
+        // the system is installed by the next call
+        let (strong2, _) = resources.world_mut().spawn_ref_counted();
+        let id2 = strong2.id();
+        drop(strong2);
+        run_schedule(&mut resources);
+        assert!(!resources.world().entities().contains(id));
+        assert!(!resources.world().entities().contains(id2));
+    }
+}
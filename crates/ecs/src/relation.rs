@@ -0,0 +1,510 @@
+use std::{borrow::Cow, marker::PhantomData};
+
+use pulz_schedule::resource::{ResourceAccess, ResourceId};
+use slotmap::SparseSecondaryMap;
+
+use crate::{
+    archetype::{Archetype, ArchetypeId},
+    component::{Component, ComponentDetails, Components},
+    entity::Entity,
+    insert_sorted,
+    query::{QueryParam, QueryParamFetch, QueryParamState, ReadOnlyQueryParam},
+    resource::{Res, Resources, ResourcesSend},
+    storage::Storage,
+};
+
+/// A component with the targets of the relation `R` of an entity.
+///
+/// `R` is a marker type for the kind of the relation (like `DockedAt` or
+/// `Owns`). An entity can have multiple targets for the same relation kind.
+///
+/// The relations are stored in a [`RelationStorage`], that also maintains
+/// the reverse direction (see [`RelatedTo`]). Relations are removed, when the
+/// source or the target is despawned.
+///
+/// Relations can't be borrowed mutably. They are changed by inserting a new
+/// `Relation`, or with [`EntityMut::relate`](crate::entity::EntityMut::relate)
+/// and [`EntityMut::unrelate`](crate::entity::EntityMut::unrelate).
+pub struct Relation<R> {
+    targets: Vec<Entity>,
+    _kind: PhantomData<fn() -> R>,
+}
+
+/// Query for the targets of the relation `R` of an entity.
+pub type Related<R> = &'static Relation<R>;
+
+impl<R> Relation<R> {
+    /// Creates a relation with a single target.
+    #[inline]
+    pub fn new(target: Entity) -> Self {
+        Self {
+            targets: vec![target],
+            _kind: PhantomData,
+        }
+    }
+
+    /// Creates a relation with multiple targets.
+    pub fn from_targets(targets: impl IntoIterator<Item = Entity>) -> Self {
+        let mut targets: Vec<Entity> = targets.into_iter().collect();
+        targets.sort_unstable();
+        targets.dedup();
+        Self {
+            targets,
+            _kind: PhantomData,
+        }
+    }
+
+    /// Returns the first target of this relation.
+    ///
+    /// # Panics
+    /// Panics when the relation has no targets.
+    #[inline]
+    pub fn target(&self) -> Entity {
+        self.targets[0]
+    }
+
+    /// Returns all targets of this relation, ordered by their id.
+    #[inline]
+    pub fn targets(&self) -> &[Entity] {
+        &self.targets
+    }
+}
+
+impl<R> Clone for Relation<R> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            targets: self.targets.clone(),
+            _kind: PhantomData,
+        }
+    }
+}
+
+impl<R> std::fmt::Debug for Relation<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Relation").field(&self.targets).finish()
+    }
+}
+
+impl<R> std::ops::Deref for Relation<R> {
+    type Target = [Entity];
+    #[inline]
+    fn deref(&self) -> &[Entity] {
+        &self.targets
+    }
+}
+
+impl<R: 'static> Component for Relation<R> {
+    type Storage = RelationStorage<R>;
+}
+
+/// The storage of [`Relation`] components, with an index of the sources of
+/// every target.
+pub struct RelationStorage<R> {
+    relations: SparseSecondaryMap<Entity, Relation<R>>,
+    sources: SparseSecondaryMap<Entity, Vec<Entity>>,
+}
+
+impl<R> Default for RelationStorage<R> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            relations: SparseSecondaryMap::new(),
+            sources: SparseSecondaryMap::new(),
+        }
+    }
+}
+
+impl<R> RelationStorage<R> {
+    /// Returns the targets of the relation of `source`, ordered by their id.
+    #[inline]
+    pub fn targets(&self, source: Entity) -> &[Entity] {
+        self.relations.get(source).map_or(&[], |r| &r.targets)
+    }
+
+    /// Returns the entities with a relation to `target`, ordered by their id.
+    #[inline]
+    pub fn sources(&self, target: Entity) -> &[Entity] {
+        self.sources.get(target).map_or(&[], Vec::as_slice)
+    }
+
+    fn link(&mut self, source: Entity, target: Entity) {
+        if let Some(sources) = self.sources.get_mut(target) {
+            insert_sorted(sources, source);
+        } else {
+            self.sources.insert(target, vec![source]);
+        }
+    }
+
+    fn unlink(&mut self, source: Entity, target: Entity) {
+        if let Some(sources) = self.sources.get_mut(target) {
+            if let Ok(pos) = sources.binary_search(&source) {
+                sources.remove(pos);
+            }
+            if sources.is_empty() {
+                self.sources.remove(target);
+            }
+        }
+    }
+
+    /// Adds `target` to the relation of `source`.
+    pub(crate) fn add(&mut self, source: Entity, target: Entity) {
+        if let Some(relation) = self.relations.get_mut(source) {
+            insert_sorted(&mut relation.targets, target);
+        } else {
+            self.relations.insert(source, Relation::new(target));
+        }
+        self.link(source, target);
+    }
+
+    /// Removes `target` from the relation of `source`, and removes the whole
+    /// relation, when it was the last target.
+    pub(crate) fn remove_target(&mut self, source: Entity, target: Entity) {
+        let Some(relation) = self.relations.get_mut(source) else {
+            return;
+        };
+        if let Ok(pos) = relation.targets.binary_search(&target) {
+            relation.targets.remove(pos);
+            if relation.targets.is_empty() {
+                self.relations.remove(source);
+            }
+            self.unlink(source, target);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, source: Entity) -> Option<Relation<R>> {
+        let relation = self.relations.remove(source)?;
+        for &target in &relation.targets {
+            self.unlink(source, target);
+        }
+        Some(relation)
+    }
+}
+
+impl<R: 'static> Storage for RelationStorage<R> {
+    const SPARSE: bool = true;
+    const MUTABLE: bool = false;
+    type Component = Relation<R>;
+
+    #[inline]
+    fn fast_contains(
+        res: &Resources,
+        entity: Entity,
+        component: &ComponentDetails,
+        _archetype: &Archetype,
+    ) -> bool {
+        res.borrow_res_id(component.storage_id.typed::<Self>())
+            .map_or(false, |s| s.relations.contains_key(entity))
+    }
+
+    #[inline]
+    fn contains(&self, entity: Entity, _archetype: ArchetypeId, _index: usize) -> bool {
+        self.relations.contains_key(entity)
+    }
+
+    #[inline]
+    fn swap_remove(
+        &mut self,
+        entity: Entity,
+        _archetype: ArchetypeId,
+        _index: usize,
+    ) -> Option<Relation<R>> {
+        self.remove(entity)
+    }
+
+    fn despawn(
+        &mut self,
+        entity: Entity,
+        _archetype: ArchetypeId,
+        _index: usize,
+    ) -> Option<Relation<R>> {
+        // remove the relations to the despawned entity
+        if let Some(sources) = self.sources.remove(entity) {
+            for source in sources {
+                if let Some(relation) = self.relations.get_mut(source) {
+                    relation.targets.retain(|&t| t != entity);
+                    if relation.targets.is_empty() {
+                        self.relations.remove(source);
+                    }
+                }
+            }
+        }
+        self.remove(entity)
+    }
+
+    fn insert(&mut self, entity: Entity, mut value: Relation<R>) {
+        self.remove(entity);
+        value.targets.sort_unstable();
+        value.targets.dedup();
+        if value.targets.is_empty() {
+            return;
+        }
+        for &target in &value.targets {
+            self.link(entity, target);
+        }
+        self.relations.insert(entity, value);
+    }
+
+    #[inline]
+    fn flush_replace(&mut self, _entity: Entity, _archetype: ArchetypeId, _index: usize) -> bool {
+        true
+    }
+
+    #[inline]
+    fn flush_push(&mut self, _entity: Entity, _archetype: ArchetypeId) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn swap_remove_and_insert(
+        &mut self,
+        _remove_from_archetype: ArchetypeId,
+        _remove_from_index: usize,
+        _insert_to_archetype: ArchetypeId,
+    ) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn get(&self, entity: Entity, _archetype: ArchetypeId, _index: usize) -> Option<&Relation<R>> {
+        self.relations.get(entity)
+    }
+
+    /// Relations can't be borrowed mutably, because this would bypass the
+    /// index of the sources.
+    #[inline]
+    fn get_mut(
+        &mut self,
+        _entity: Entity,
+        _archetype: ArchetypeId,
+        _index: usize,
+    ) -> Option<&mut Relation<R>> {
+        None
+    }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        Some(self.relations.len())
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        entities.extend(self.relations.keys());
+    }
+}
+
+/// Query for the entities with a relation `R` to an entity (the reverse
+/// direction of [`Related`]).
+///
+/// Only entities that are the target of at least one relation `R` are
+/// matched. The sources are looked up in the index of the
+/// [`RelationStorage`], without scanning all relations.
+pub struct RelatedTo<R>(PhantomData<fn() -> R>);
+
+impl<R: 'static> QueryParam for RelatedTo<R> {
+    type State = QryRelatedToState<R>;
+    type Fetch<'w> = QryRelatedToFetch<'w, R>;
+}
+
+// SAFETY: only shared access
+unsafe impl<R: 'static> ReadOnlyQueryParam for RelatedTo<R> {}
+
+#[doc(hidden)]
+pub struct QryRelatedToState<R: 'static> {
+    storage_id: ResourceId<RelationStorage<R>>,
+    name: String,
+}
+
+unsafe impl<R: 'static> QueryParamState for QryRelatedToState<R> {
+    #[inline]
+    fn init(_res: &Resources, components: &Components) -> Self {
+        let component_id = components.expect_id::<Relation<R>>();
+        let component = components.get(component_id).unwrap();
+        Self {
+            storage_id: component.storage_id.typed(),
+            name: component.name().to_owned(),
+        }
+    }
+
+    #[inline]
+    fn is_registered(components: &Components) -> bool {
+        components.id::<Relation<R>>().is_some()
+    }
+
+    #[inline]
+    fn update_access(&self, access: &mut ResourceAccess) {
+        access.add_shared_checked(self.storage_id);
+        access.set_name(self.storage_id, self.name.clone());
+    }
+
+    #[inline]
+    fn matches_archetype(&self, _archetype: &Archetype) -> bool {
+        true
+    }
+
+    fn type_name(&self) -> Cow<'static, str> {
+        format!("RelatedTo<{}>", std::any::type_name::<R>()).into()
+    }
+}
+
+#[doc(hidden)]
+pub struct QryRelatedToFetch<'w, R: 'static>(Res<'w, RelationStorage<R>>);
+
+impl<'w, R: 'static> QueryParamFetch<'w> for QryRelatedToFetch<'w, R> {
+    type State = QryRelatedToState<R>;
+    type Item<'a>
+        = &'a [Entity]
+    where
+        Self: 'a;
+
+    #[inline]
+    fn fetch(res: &'w ResourcesSend, state: &QryRelatedToState<R>) -> Self {
+        Self(
+            res.borrow_res_id(state.storage_id)
+                .expect("unable to borrow relation"),
+        )
+    }
+
+    #[inline(always)]
+    fn set_archetype(&mut self, _state: &Self::State, _archetype: &Archetype) {}
+
+    #[inline]
+    fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_> {
+        self.0.sources(archetype.entities[index])
+    }
+
+    #[inline]
+    fn matches_entity(&self, archetype: &Archetype, index: usize) -> bool {
+        self.0.sources.contains_key(archetype.entities[index])
+    }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        Some(self.0.sources.len())
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        entities.extend(self.0.sources.keys());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pulz_schedule::resource::Resources;
+
+    use super::{Related, RelatedTo, Relation};
+    use crate::{component::Component, entity::Entity, query::Query, WorldExt};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    struct Ship(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    struct Station;
+
+    struct DockedAt;
+    struct Targets;
+
+    fn docked(resources: &mut Resources) -> Vec<(usize, Entity)> {
+        let mut query = Query::<(&Ship, Related<DockedAt>)>::new(resources);
+        let mut result: Vec<_> = query.iter().map(|(s, r)| (s.0, r.target())).collect();
+        result.sort_unstable();
+        result
+    }
+
+    fn sources(resources: &mut Resources, station: Entity) -> Vec<Entity> {
+        let mut query = Query::<RelatedTo<DockedAt>>::new(resources);
+        query.get(station).map_or_else(Vec::new, <[Entity]>::to_vec)
+    }
+
+    #[test]
+    fn test_relation() {
+        let mut resources = Resources::new();
+        let (s1, s2, ships) = {
+            let mut world = resources.world_mut();
+            let s1 = world.spawn().insert(Station).id();
+            let s2 = world.spawn().insert(Station).id();
+            let ships: Vec<Entity> = (0..4)
+                .map(|i| {
+                    let station = if i < 3 { s1 } else { s2 };
+                    world
+                        .spawn()
+                        .insert(Ship(i))
+                        .insert(Relation::<DockedAt>::new(station))
+                        .id()
+                })
+                .collect();
+            (s1, s2, ships)
+        };
+
+        assert_eq!(
+            vec![(0, s1), (1, s1), (2, s1), (3, s2)],
+            docked(&mut resources)
+        );
+        let mut expected = ships[..3].to_vec();
+        expected.sort_unstable();
+        assert_eq!(expected, sources(&mut resources, s1));
+        assert_eq!(vec![ships[3]], sources(&mut resources, s2));
+
+        // replace the relation
+        resources
+            .world_mut()
+            .entity_mut(ships[0])
+            .unwrap()
+            .insert(Relation::<DockedAt>::new(s2));
+        assert_eq!(
+            vec![(0, s2), (1, s1), (2, s1), (3, s2)],
+            docked(&mut resources)
+        );
+        assert_eq!(2, sources(&mut resources, s1).len());
+
+        // despawn a source
+        resources.world_mut().despawn(ships[1]);
+        assert_eq!(vec![ships[2]], sources(&mut resources, s1));
+
+        // despawn a target
+        resources.world_mut().despawn(s2);
+        assert_eq!(vec![(2, s1)], docked(&mut resources));
+        assert!(sources(&mut resources, s2).is_empty());
+    }
+
+    #[test]
+    fn test_relation_many_to_many() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let [a, b, c] = [0, 1, 2].map(|i| world.spawn().insert(Ship(i)).id());
+        world
+            .entity_mut(a)
+            .unwrap()
+            .relate::<Targets>(b)
+            .relate::<Targets>(c);
+        world.entity_mut(b).unwrap().relate::<Targets>(c);
+        world.entity_mut(c).unwrap().relate::<Targets>(a);
+        drop(world);
+
+        let mut query =
+            Query::<(Entity, Related<Targets>, RelatedTo<Targets>)>::new(&mut resources);
+        for (entity, targets, sources) in query.iter() {
+            let (mut expected_targets, mut expected_sources) = match entity {
+                e if e == a => (vec![b, c], vec![c]),
+                e if e == b => (vec![c], vec![a]),
+                _ => (vec![a], vec![a, b]),
+            };
+            expected_targets.sort_unstable();
+            expected_sources.sort_unstable();
+            assert_eq!(expected_targets, targets.targets());
+            assert_eq!(expected_sources, sources);
+        }
+        drop(query);
+
+        let mut world = resources.world_mut();
+        world.entity_mut(a).unwrap().unrelate::<Targets>(b);
+        world.entity_mut(c).unwrap().unrelate::<Targets>(a);
+        assert!(!world.entity(c).unwrap().contains::<Relation<Targets>>());
+        let entity = world.entity(a).unwrap();
+        assert_eq!(
+            &[c],
+            entity.borrow::<Relation<Targets>>().unwrap().targets()
+        );
+    }
+}
@@ -0,0 +1,496 @@
+use std::borrow::Cow;
+
+use pulz_schedule::resource::{AccessPartition, ResourceAccess, ResourceId};
+use slotmap::SparseSecondaryMap;
+
+use crate::{
+    archetype::{Archetype, ArchetypeId},
+    component::{Component, ComponentDetails, ComponentId, Components},
+    query::{QueryParamChunkFetch, QueryParamFetch, QueryParamState},
+    resource::{Res, ResMut, Resources, ResourcesSend},
+    storage::{vec_make_available, Storage},
+    Entity,
+};
+
+/// A component that is stored as a struct of arrays, with a separate column
+/// for every field (see [`SoaStorage`]).
+///
+/// This is implemented by `#[derive(Component)]` with `#[component(soa)]` for
+/// structs with named fields. For a `Particle`, the derive also generates the
+/// proxies `ParticleRef` and `ParticleMut` (with a reference to every field),
+/// that are used in queries instead of `&Particle` and `&mut Particle`, and
+/// `ParticleSlices` and `ParticleSlicesMut`, that are the chunks of these
+/// proxies in chunk iteration.
+pub trait SoaComponent: Sized + Send + Sync + 'static {
+    /// The columns of the fields of an archetype.
+    type Columns: Default + Send + Sync + 'static;
+    /// Shared references to the fields of a component.
+    type Ref<'a>;
+    /// Mutable references to the fields of a component.
+    type Mut<'a>;
+    /// The columns of the fields as slices.
+    type Slices<'a>;
+    /// The columns of the fields as mutable slices.
+    type SlicesMut<'a>;
+
+    #[doc(hidden)]
+    fn len(columns: &Self::Columns) -> usize;
+    #[doc(hidden)]
+    fn push(columns: &mut Self::Columns, value: Self);
+    #[doc(hidden)]
+    fn swap_remove(columns: &mut Self::Columns, index: usize) -> Self;
+    #[doc(hidden)]
+    fn replace(columns: &mut Self::Columns, index: usize, value: Self) -> Self;
+    #[doc(hidden)]
+    fn get(columns: &Self::Columns, index: usize) -> Self::Ref<'_>;
+    #[doc(hidden)]
+    fn get_mut(columns: &mut Self::Columns, index: usize) -> Self::Mut<'_>;
+    #[doc(hidden)]
+    fn slices(columns: &Self::Columns) -> Self::Slices<'_>;
+    #[doc(hidden)]
+    fn slices_mut(columns: &mut Self::Columns) -> Self::SlicesMut<'_>;
+}
+
+/// A storage for [`SoaComponent`]s, that stores every field of the components
+/// of an archetype in a separate column.
+///
+/// The components themselves are never stored, so they can't be borrowed with
+/// [`get`](Storage::get) or [`get_mut`](Storage::get_mut), and queries for
+/// `&T` or `&mut T` are rejected (see [`Storage::BORROWABLE`]). Use the
+/// generated proxies instead (for example `ParticleRef` and `ParticleMut` for
+/// a `Particle`).
+pub struct SoaStorage<T: SoaComponent> {
+    data: Vec<T::Columns>,
+    /// values that are inserted, but not yet moved into their archetype
+    staged: SparseSecondaryMap<Entity, T>,
+    /// the columns of archetypes without components
+    empty: T::Columns,
+}
+
+impl<T: SoaComponent> Default for SoaStorage<T> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            data: Vec::new(),
+            staged: SparseSecondaryMap::new(),
+            empty: T::Columns::default(),
+        }
+    }
+}
+
+impl<T: SoaComponent> SoaStorage<T> {
+    #[inline]
+    fn columns(&self, archetype: ArchetypeId) -> &T::Columns {
+        self.data.get(archetype.index()).unwrap_or(&self.empty)
+    }
+
+    #[inline]
+    fn columns_mut(&mut self, archetype: ArchetypeId) -> &mut T::Columns {
+        self.data
+            .get_mut(archetype.index())
+            .unwrap_or(&mut self.empty)
+    }
+
+    /// Returns the fields of the component at the given location.
+    #[inline]
+    pub fn get_ref(&self, archetype: ArchetypeId, index: usize) -> Option<T::Ref<'_>> {
+        let columns = self.columns(archetype);
+        (index < T::len(columns)).then(|| T::get(columns, index))
+    }
+
+    /// Returns the fields of the component at the given location.
+    #[inline]
+    pub fn get_mut_ref(&mut self, archetype: ArchetypeId, index: usize) -> Option<T::Mut<'_>> {
+        let columns = self.columns_mut(archetype);
+        if index < T::len(columns) {
+            Some(T::get_mut(columns, index))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the columns of the fields of the given archetype.
+    #[inline]
+    pub fn slices(&self, archetype: ArchetypeId) -> T::Slices<'_> {
+        T::slices(self.columns(archetype))
+    }
+
+    /// Returns the columns of the fields of the given archetype.
+    #[inline]
+    pub fn slices_mut(&mut self, archetype: ArchetypeId) -> T::SlicesMut<'_> {
+        T::slices_mut(self.columns_mut(archetype))
+    }
+}
+
+impl<T: SoaComponent> Storage for SoaStorage<T> {
+    const SPARSE: bool = false;
+    const MUTABLE: bool = false;
+    const BORROWABLE: bool = false;
+    type Component = T;
+
+    #[inline]
+    fn fast_contains(
+        _res: &Resources,
+        _entity: Entity,
+        component: &ComponentDetails,
+        archetype: &Archetype,
+    ) -> bool {
+        archetype.components.contains(component.id())
+    }
+
+    #[inline]
+    fn contains(&self, _entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        self.data
+            .get(archetype.index())
+            .map_or(false, |c| index < T::len(c))
+    }
+
+    #[inline]
+    fn swap_remove(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> Option<T> {
+        self.staged.remove(entity);
+        let columns = self.data.get_mut(archetype.index())?;
+        (index < T::len(columns)).then(|| T::swap_remove(columns, index))
+    }
+
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) {
+        self.staged.insert(entity, value);
+    }
+
+    fn flush_replace(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        if !self.contains(entity, archetype, index) {
+            return false;
+        }
+        let Some(value) = self.staged.remove(entity) else {
+            return false;
+        };
+        T::replace(&mut self.data[archetype.index()], index, value);
+        true
+    }
+
+    fn flush_push(&mut self, entity: Entity, archetype: ArchetypeId) -> Option<usize> {
+        let value = self.staged.remove(entity)?;
+        let columns = vec_make_available(&mut self.data, archetype.index());
+        let index = T::len(columns);
+        T::push(columns, value);
+        Some(index)
+    }
+
+    fn swap_remove_and_insert(
+        &mut self,
+        remove_from_archetype: ArchetypeId,
+        remove_from_index: usize,
+        insert_to_archetype: ArchetypeId,
+    ) -> Option<usize> {
+        if remove_from_archetype == insert_to_archetype {
+            return None;
+        }
+        let columns = self.data.get_mut(remove_from_archetype.index())?;
+        if remove_from_index >= T::len(columns) {
+            return None;
+        }
+        let value = T::swap_remove(columns, remove_from_index);
+        let columns = vec_make_available(&mut self.data, insert_to_archetype.index());
+        let index = T::len(columns);
+        T::push(columns, value);
+        Some(index)
+    }
+
+    /// The components are not stored as a whole.
+    #[inline]
+    fn get(&self, _entity: Entity, _archetype: ArchetypeId, _index: usize) -> Option<&T> {
+        None
+    }
+
+    /// The components are not stored as a whole.
+    #[inline]
+    fn get_mut(
+        &mut self,
+        _entity: Entity,
+        _archetype: ArchetypeId,
+        _index: usize,
+    ) -> Option<&mut T> {
+        None
+    }
+}
+
+/// The state of the query proxies of a [`SoaComponent`] (shared when `MUT` is
+/// `false`).
+#[doc(hidden)]
+pub struct QrySoaState<T: SoaComponent, const MUT: bool> {
+    storage_id: ResourceId<SoaStorage<T>>,
+    component_id: ComponentId<T>,
+    name: String,
+}
+
+unsafe impl<T, const MUT: bool> QueryParamState for QrySoaState<T, MUT>
+where
+    T: SoaComponent + Component<Storage = SoaStorage<T>>,
+{
+    #[inline]
+    fn init(_res: &Resources, components: &Components) -> Self {
+        let component_id = components.expect_id::<T>();
+        let component = components.get(component_id).unwrap();
+        Self {
+            storage_id: component.storage_id.typed(),
+            component_id,
+            name: component.name().to_owned(),
+        }
+    }
+
+    #[inline]
+    fn is_registered(components: &Components) -> bool {
+        components.id::<T>().is_some()
+    }
+
+    #[inline]
+    fn update_access(&self, access: &mut ResourceAccess) {
+        if MUT {
+            access.add_exclusive_checked(self.storage_id);
+        } else {
+            access.add_shared_checked(self.storage_id);
+        }
+        access.set_name(self.storage_id, self.name.clone());
+    }
+
+    #[inline]
+    fn matches_archetype(&self, archetype: &Archetype) -> bool {
+        archetype.contains_component_id(self.component_id)
+    }
+
+    #[inline]
+    fn update_partition(&self, partition: &mut AccessPartition) {
+        partition.with.insert(self.component_id.offset());
+    }
+
+    fn type_name(&self) -> Cow<'static, str> {
+        if MUT {
+            format!("{}Mut", self.name).into()
+        } else {
+            format!("{}Ref", self.name).into()
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct QrySoaRefFetch<'w, T: SoaComponent>(Res<'w, SoaStorage<T>>);
+
+impl<'w, T> QueryParamFetch<'w> for QrySoaRefFetch<'w, T>
+where
+    T: SoaComponent + Component<Storage = SoaStorage<T>>,
+{
+    type State = QrySoaState<T, false>;
+    type Item<'a>
+        = T::Ref<'a>
+    where
+        Self: 'a;
+
+    #[inline]
+    fn fetch(res: &'w ResourcesSend, state: &Self::State) -> Self {
+        Self(
+            res.borrow_res_id(state.storage_id)
+                .expect("unable to borrow component"),
+        )
+    }
+
+    #[inline(always)]
+    fn set_archetype(&mut self, _state: &Self::State, _archetype: &Archetype) {}
+
+    #[inline]
+    fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_> {
+        self.0
+            .get_ref(archetype.id, index)
+            .expect("unable to get component item")
+    }
+}
+
+impl<'w, T> QueryParamChunkFetch<'w> for QrySoaRefFetch<'w, T>
+where
+    T: SoaComponent + Component<Storage = SoaStorage<T>>,
+{
+    type Chunk<'a>
+        = T::Slices<'a>
+    where
+        Self: 'a;
+
+    #[inline]
+    fn get_chunk<'a>(&'a mut self, archetype: &'a Archetype) -> Self::Chunk<'a> {
+        self.0.slices(archetype.id)
+    }
+}
+
+#[doc(hidden)]
+pub struct QrySoaMutFetch<'w, T: SoaComponent>(ResMut<'w, SoaStorage<T>>);
+
+impl<'w, T> QueryParamFetch<'w> for QrySoaMutFetch<'w, T>
+where
+    T: SoaComponent + Component<Storage = SoaStorage<T>>,
+{
+    type State = QrySoaState<T, true>;
+    type Item<'a>
+        = T::Mut<'a>
+    where
+        Self: 'a;
+
+    #[inline]
+    fn fetch(res: &'w ResourcesSend, state: &Self::State) -> Self {
+        Self(
+            res.borrow_res_mut_id(state.storage_id)
+                .expect("unable to borrow mut component"),
+        )
+    }
+
+    #[inline(always)]
+    fn set_archetype(&mut self, _state: &Self::State, _archetype: &Archetype) {}
+
+    #[inline]
+    fn get(&mut self, archetype: &Archetype, index: usize) -> Self::Item<'_> {
+        self.0
+            .get_mut_ref(archetype.id, index)
+            .expect("unable to get component item")
+    }
+}
+
+impl<'w, T> QueryParamChunkFetch<'w> for QrySoaMutFetch<'w, T>
+where
+    T: SoaComponent + Component<Storage = SoaStorage<T>>,
+{
+    type Chunk<'a>
+        = T::SlicesMut<'a>
+    where
+        Self: 'a;
+
+    #[inline]
+    fn get_chunk<'a>(&'a mut self, archetype: &'a Archetype) -> Self::Chunk<'a> {
+        self.0.slices_mut(archetype.id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pulz_schedule::resource::Resources;
+
+    use super::SoaStorage;
+    use crate::{component::Component, entity::Entity, query::Query, WorldExt};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Component)]
+    #[component(soa)]
+    struct Particle {
+        pos: [f32; 3],
+        vel: [f32; 3],
+        life: f32,
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    struct A(usize);
+
+    fn particle(life: f32) -> Particle {
+        Particle {
+            pos: [life, 0.0, 0.0],
+            vel: [0.0, life, 0.0],
+            life,
+        }
+    }
+
+    fn lives(resources: &mut Resources, entities: &[Entity]) -> Vec<Option<f32>> {
+        let mut query = Query::<(Entity, ParticleRef<'_>)>::new(resources);
+        let mut lives = vec![None; entities.len()];
+        for (e, p) in query.iter() {
+            // the fields of a particle are moved together
+            assert_eq!([*p.life, 0.0, 0.0], *p.pos);
+            assert_eq!([0.0, *p.life, 0.0], *p.vel);
+            let i = entities.iter().position(|&x| x == e).unwrap();
+            lives[i] = Some(*p.life);
+        }
+        lives
+    }
+
+    #[test]
+    fn test_soa_storage() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let entities: Vec<Entity> = (0..6)
+            .map(|i| world.spawn().insert(particle(i as f32)).id())
+            .collect();
+        // move some of the particles to other archetypes
+        for &e in entities.iter().step_by(2) {
+            world.entity_mut(e).unwrap().insert(A(0));
+        }
+        world.entity_mut(entities[2]).unwrap().remove::<A>();
+        world
+            .entity_mut(entities[3])
+            .unwrap()
+            .insert(particle(30.0));
+        world.despawn(entities[1]);
+        world.entity_mut(entities[5]).unwrap().remove::<Particle>();
+        drop(world);
+
+        assert_eq!(
+            vec![Some(0.0), None, Some(2.0), Some(30.0), Some(4.0), None],
+            lives(&mut resources, &entities)
+        );
+        let storage = resources.borrow_res::<SoaStorage<Particle>>().unwrap();
+        assert!(storage.staged.is_empty());
+    }
+
+    #[test]
+    fn test_soa_query() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let entities: Vec<Entity> = (0..4)
+            .map(|i| world.spawn().insert(particle(i as f32)).id())
+            .collect();
+        world.entity_mut(entities[0]).unwrap().insert(A(0));
+        drop(world);
+
+        let mut query = Query::<ParticleMut<'_>>::new(&mut resources);
+        for p in query.iter() {
+            *p.life += 1.0;
+            p.pos[0] += 1.0;
+            p.vel[1] += 1.0;
+        }
+        drop(query);
+        assert_eq!(
+            vec![Some(1.0), Some(2.0), Some(3.0), Some(4.0)],
+            lives(&mut resources, &entities)
+        );
+
+        // per-field slices in chunk iteration
+        let mut query = Query::<ParticleMut<'_>>::new(&mut resources);
+        let mut chunks = 0;
+        query.for_each_chunk(|p| {
+            chunks += 1;
+            p.life.iter_mut().for_each(|life| *life *= 2.0);
+        });
+        drop(query);
+        assert_eq!(2, chunks);
+        let mut query = Query::<ParticleRef<'_>>::new(&mut resources);
+        let mut total = 0.0;
+        query.for_each_chunk(|p| total += p.life.iter().sum::<f32>());
+        assert_eq!(20.0, total);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be queried with `&`")]
+    fn test_soa_query_whole_component() {
+        let mut resources = Resources::new();
+        resources.world_mut().spawn().insert(particle(1.0));
+        let _ = Query::<&Particle>::new(&mut resources);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be cloned")]
+    fn test_soa_not_clonable() {
+        let mut resources = Resources::new();
+        resources.world_mut().init_clonable::<Particle>();
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be borrowed")]
+    fn test_soa_borrow_whole_component() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let e = world.spawn().insert(particle(1.0)).id();
+        let _ = world.entity(e).unwrap().borrow::<Particle>();
+    }
+}
@@ -0,0 +1,399 @@
+use std::{any::TypeId, cmp::Ordering};
+
+use pulz_schedule::resource::Resources;
+use slotmap::Key;
+
+use crate::{
+    archetype::{Archetype, ArchetypeId},
+    component::ComponentDetails,
+    storage::Storage,
+    Entity,
+};
+
+/// The number of entries in a page of the sparse index.
+const PAGE_SIZE: usize = 1024;
+
+/// Marks an empty entry of the sparse index.
+const EMPTY: usize = usize::MAX;
+
+/// A sparse storage with a paged sparse index, and packed dense arrays of the
+/// components and their entities.
+///
+/// Unlike [`SparseStorage`](crate::storage::SparseStorage), lookups don't
+/// need hashing, and iteration (also of queries that are driven by this
+/// storage) walks the dense arrays. The order of the dense arrays can be
+/// changed with [`sort_by`](Self::sort_by); removing a component moves the
+/// last component into its place.
+///
+/// The storage can be owned by a group (see
+/// [`WorldMut::create_group`](crate::world::WorldMut::create_group)), that
+/// keeps the components of its entities at the front of the dense arrays.
+pub struct SparseSetStorage<T> {
+    /// the index into the dense arrays, by the index of the entity
+    sparse: Vec<Option<Box<[usize; PAGE_SIZE]>>>,
+    entities: Vec<Entity>,
+    dense: Vec<T>,
+    /// the type of the group owning this storage
+    group: Option<TypeId>,
+    group_len: usize,
+}
+
+impl<T> Default for SparseSetStorage<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[inline]
+fn sparse_index(entity: Entity) -> (usize, usize) {
+    // the lower 32 bits are the index of the slot
+    let index = (entity.data().as_ffi() & 0xffff_ffff) as usize;
+    (index / PAGE_SIZE, index % PAGE_SIZE)
+}
+
+impl<T> SparseSetStorage<T> {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            dense: Vec::new(),
+            group: None,
+            group_len: 0,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    /// Returns the index of the component of `entity` in the dense arrays.
+    #[inline]
+    fn dense_index(&self, entity: Entity) -> Option<usize> {
+        let (page, offset) = sparse_index(entity);
+        let index = self.sparse.get(page)?.as_ref()?[offset];
+        // also rejects entities with an other version
+        (index != EMPTY && self.entities[index] == entity).then_some(index)
+    }
+
+    #[inline]
+    fn set_dense_index(&mut self, entity: Entity, index: usize) {
+        let (page, offset) = sparse_index(entity);
+        if self.sparse.len() <= page {
+            self.sparse.resize_with(page + 1, || None);
+        }
+        self.sparse[page].get_or_insert_with(|| Box::new([EMPTY; PAGE_SIZE]))[offset] = index;
+    }
+
+    /// Swaps two components in the dense arrays.
+    fn swap_dense(&mut self, a: usize, b: usize) {
+        if a != b {
+            self.dense.swap(a, b);
+            self.entities.swap(a, b);
+            self.set_dense_index(self.entities[a], a);
+            self.set_dense_index(self.entities[b], b);
+        }
+    }
+
+    /// Returns the number of components at the front of the dense arrays,
+    /// that belong to the entities of the group owning this storage.
+    #[inline]
+    pub fn group_len(&self) -> usize {
+        self.group_len
+    }
+
+    #[inline]
+    pub(crate) fn group(&self) -> Option<TypeId> {
+        self.group
+    }
+
+    #[inline]
+    pub(crate) fn set_group(&mut self, group: TypeId) {
+        self.group = Some(group);
+    }
+
+    /// Moves the component of `entity` to the end of the group.
+    pub(crate) fn enter_group(&mut self, entity: Entity) {
+        if let Some(index) = self.dense_index(entity) {
+            if index >= self.group_len {
+                self.swap_dense(index, self.group_len);
+                self.group_len += 1;
+            }
+        }
+    }
+
+    /// Moves the component of `entity` out of the group. The last component
+    /// of the group is moved into its place.
+    pub(crate) fn leave_group(&mut self, entity: Entity) {
+        if let Some(index) = self.dense_index(entity) {
+            if index < self.group_len {
+                self.group_len -= 1;
+                self.swap_dense(index, self.group_len);
+            }
+        }
+    }
+
+    #[inline]
+    pub fn contains_key(&self, entity: Entity) -> bool {
+        self.dense_index(entity).is_some()
+    }
+
+    #[inline]
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        let index = self.dense_index(entity)?;
+        Some(&self.dense[index])
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        let index = self.dense_index(entity)?;
+        Some(&mut self.dense[index])
+    }
+
+    /// Inserts the component of `entity`, and returns the replaced component.
+    pub fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        if let Some(index) = self.dense_index(entity) {
+            return Some(std::mem::replace(&mut self.dense[index], value));
+        }
+        self.set_dense_index(entity, self.dense.len());
+        self.entities.push(entity);
+        self.dense.push(value);
+        None
+    }
+
+    /// Removes the component of `entity`. The last component of the dense
+    /// arrays is moved into its place.
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.leave_group(entity);
+        let index = self.dense_index(entity)?;
+        self.set_dense_index(entity, EMPTY);
+        self.entities.swap_remove(index);
+        let value = self.dense.swap_remove(index);
+        if let Some(&moved) = self.entities.get(index) {
+            self.set_dense_index(moved, index);
+        }
+        Some(value)
+    }
+
+    /// Returns the entities in the order of the dense array.
+    #[inline]
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// Returns the components in the order of the dense array.
+    #[inline]
+    pub fn values(&self) -> &[T] {
+        &self.dense
+    }
+
+    /// Returns the components in the order of the dense array.
+    #[inline]
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.dense
+    }
+
+    /// Iterates the entities and their components in the order of the dense
+    /// array.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> + '_ {
+        self.entities.iter().copied().zip(self.dense.iter())
+    }
+
+    /// Iterates the entities and their components in the order of the dense
+    /// array.
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> + '_ {
+        self.entities.iter().copied().zip(self.dense.iter_mut())
+    }
+
+    /// Sorts the dense arrays with the given comparator (stable).
+    ///
+    /// Queries that are driven by this storage visit the entities in this
+    /// order. When the storage is owned by a group, only the components
+    /// after the group are sorted.
+    pub fn sort_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let first = self.group_len;
+        // `order[i]` is the current index of the component, that is moved to `i`
+        let mut order: Vec<usize> = (0..self.dense.len()).collect();
+        order[first..].sort_by(|&a, &b| compare(&self.dense[a], &self.dense[b]));
+        // apply the permutation by following its cycles
+        for start in first..order.len() {
+            let mut current = start;
+            loop {
+                let source = order[current];
+                order[current] = current;
+                if source == start || source == current {
+                    break;
+                }
+                self.dense.swap(current, source);
+                self.entities.swap(current, source);
+                current = source;
+            }
+        }
+        for index in first..self.entities.len() {
+            self.set_dense_index(self.entities[index], index);
+        }
+    }
+}
+
+impl<T> Storage for SparseSetStorage<T>
+where
+    T: Send + Sync + 'static,
+{
+    const SPARSE: bool = true;
+    type Component = T;
+
+    #[inline]
+    fn fast_contains(
+        res: &Resources,
+        entity: Entity,
+        component: &ComponentDetails,
+        _archetype: &Archetype,
+    ) -> bool {
+        res.borrow_res_id(component.storage_id.typed::<Self>())
+            .map_or(false, |s| s.contains_key(entity))
+    }
+
+    #[inline]
+    fn contains(&self, entity: Entity, _archetype: ArchetypeId, _index: usize) -> bool {
+        self.contains_key(entity)
+    }
+
+    #[inline]
+    fn swap_remove(&mut self, entity: Entity, _archetype: ArchetypeId, _index: usize) -> Option<T> {
+        self.remove(entity)
+    }
+
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) {
+        self.insert(entity, value);
+    }
+
+    #[inline]
+    fn flush_replace(&mut self, _entity: Entity, _archetype: ArchetypeId, _index: usize) -> bool {
+        true
+    }
+
+    #[inline]
+    fn flush_push(&mut self, _entity: Entity, _archetype: ArchetypeId) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn swap_remove_and_insert(
+        &mut self,
+        _remove_from_archetype: ArchetypeId,
+        _remove_from_index: usize,
+        _insert_to_archetype: ArchetypeId,
+    ) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn get(&self, entity: Entity, _archetype: ArchetypeId, _index: usize) -> Option<&T> {
+        self.get(entity)
+    }
+
+    #[inline]
+    fn get_mut(
+        &mut self,
+        entity: Entity,
+        _archetype: ArchetypeId,
+        _index: usize,
+    ) -> Option<&mut T> {
+        self.get_mut(entity)
+    }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        Some(self.len())
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        entities.extend_from_slice(&self.entities);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pulz_schedule::resource::Resources;
+
+    use super::SparseSetStorage;
+    use crate::{component::Component, entity::Entity, query::Query, WorldExt};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    struct A(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(storage = SparseSetStorage)]
+    struct Depth(i32);
+
+    #[test]
+    fn test_sparse_set() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let entities: Vec<Entity> = (0..2000).map(|i| world.spawn().insert(A(i)).id()).collect();
+        for (i, &e) in entities.iter().enumerate().step_by(3) {
+            world.entity_mut(e).unwrap().insert(Depth(i as i32));
+        }
+        world.entity_mut(entities[3]).unwrap().remove::<Depth>();
+        world.despawn(entities[6]);
+        // reuses the slot of the despawned entity with an other version
+        let reused = world.spawn().insert(A(6)).id();
+        drop(world);
+
+        let storage = resources.borrow_res::<SparseSetStorage<Depth>>().unwrap();
+        assert_eq!(665, storage.len());
+        assert_eq!(Some(&Depth(0)), storage.get(entities[0]));
+        assert_eq!(Some(&Depth(1998)), storage.get(entities[1998]));
+        assert_eq!(None, storage.get(entities[1]));
+        assert_eq!(None, storage.get(entities[3]));
+        assert_eq!(None, storage.get(entities[6]));
+        assert_eq!(None, storage.get(reused));
+        for (e, d) in storage.iter() {
+            assert_eq!(Some(d), storage.get(e));
+        }
+    }
+
+    #[test]
+    fn test_sparse_set_sort() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        for i in 0..100 {
+            let mut e = world.spawn();
+            e.insert(A(i));
+            if i % 10 == 0 {
+                e.insert(Depth((i as i32 * 37) % 101));
+            }
+        }
+        drop(world);
+
+        resources
+            .get_mut::<SparseSetStorage<Depth>>()
+            .unwrap()
+            .sort_by(|a, b| b.0.cmp(&a.0));
+        let storage = resources.borrow_res::<SparseSetStorage<Depth>>().unwrap();
+        for (e, d) in storage.iter() {
+            assert_eq!(Some(d), storage.get(e));
+        }
+        drop(storage);
+
+        // the query is driven by the sparse set, so the sorted order is used
+        let mut query = Query::<(&A, &mut Depth)>::new(&mut resources);
+        let depths: Vec<i32> = query.iter().map(|(_, d)| d.0).collect();
+        assert_eq!(10, depths.len());
+        assert!(depths.windows(2).all(|w| w[0] >= w[1]));
+    }
+}
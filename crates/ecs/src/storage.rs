@@ -1,4 +1,9 @@
-use std::any::{Any, TypeId};
+use std::{
+    any::{Any, TypeId},
+    cell::UnsafeCell,
+    pin::Pin,
+    ptr::NonNull,
+};
 
 use pulz_schedule::{
     impl_any_cast, label::CoreSystemPhase, resource::Resources, schedule::Schedule,
@@ -8,14 +13,48 @@ use slotmap::{SecondaryMap, SparseSecondaryMap};
 use crate::{
     archetype::{Archetype, ArchetypeId},
     component::ComponentDetails,
+    index::Indexed,
     insert_sorted,
     resource::FromResourcesMut,
     Entity,
 };
+pub use crate::{
+    index::{IndexedStorage, UniqueIndexedStorage},
+    sparse_set::SparseSetStorage,
+};
 
 pub trait Storage: Send + Sync + Any + FromResourcesMut {
     const SPARSE: bool;
 
+    /// `false`, when the components can't be borrowed mutably
+    /// ([`get_mut`](Self::get_mut) always returns `None`). They can only be
+    /// replaced with [`insert`](Self::insert).
+    const MUTABLE: bool = true;
+
+    /// `false`, when the components are not stored as a whole (like the
+    /// fields of a [`SoaStorage`](crate::soa::SoaStorage)), so they can't be
+    /// borrowed: [`get`](Self::get) and [`get_mut`](Self::get_mut) always
+    /// return `None`, and queries for `&T` or `&mut T` are rejected when they
+    /// are initialized.
+    const BORROWABLE: bool = true;
+
+    /// `true`, when the components are never moved, and can be accessed with
+    /// [`get_pin_mut`](Self::get_pin_mut).
+    const PINNED: bool = false;
+
+    /// `true`, when the components are zero-sized tags without drop glue.
+    ///
+    /// Tags are only tracked by the component set of the archetypes: the
+    /// storage is never accessed for them, and the world and queries skip
+    /// them when components are inserted, moved or fetched.
+    const TAG: bool = false;
+
+    /// `true`, when the components of an archetype can be written through
+    /// [`column_ptr`](Self::column_ptr), while the storage is only borrowed
+    /// shared. Queries, that can never match the same entities, can then
+    /// write the storage at the same time.
+    const DISJOINT: bool = false;
+
     type Component;
 
     #[inline]
@@ -41,10 +80,62 @@ pub trait Storage: Send + Sync + Any + FromResourcesMut {
         index: usize,
     ) -> Option<Self::Component>;
 
+    /// Removes the component of an entity that is despawned.
+    ///
+    /// Unlike [`swap_remove`](Self::swap_remove), this is called for every
+    /// despawned entity, even when it doesn't have this component, so storages
+    /// can also remove references to the entity.
+    #[inline]
+    fn despawn(
+        &mut self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        index: usize,
+    ) -> Option<Self::Component> {
+        self.swap_remove(entity, archetype, index)
+    }
+
+    /// Like [`swap_remove`](Self::swap_remove), but the component is dropped
+    /// by the storage. Returns `true`, when a component was removed.
+    ///
+    /// Storages that drop their components in place (like [`PinnedStorage`])
+    /// can't return them from `swap_remove`, and override this instead.
+    #[inline]
+    fn swap_remove_and_drop(
+        &mut self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        index: usize,
+    ) -> bool {
+        self.swap_remove(entity, archetype, index).is_some()
+    }
+
+    /// Like [`despawn`](Self::despawn), but the component is dropped by the
+    /// storage (see [`swap_remove_and_drop`](Self::swap_remove_and_drop)).
+    #[inline]
+    fn despawn_and_drop(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        self.despawn(entity, archetype, index).is_some()
+    }
+
+    /// Inserts or stages the component of the given entity.
+    ///
+    /// Storages that depend on the archetype of the entity (like
+    /// [`ArchetypeStorage`]) only stage the value, until it is moved into place
+    /// with [`flush_replace`](Self::flush_replace) or
+    /// [`flush_push`](Self::flush_push). Values of multiple entities can be
+    /// staged at the same time, staging a value again for the same entity
+    /// replaces the staged value, and [`swap_remove`](Self::swap_remove)
+    /// discards it.
     fn insert(&mut self, entity: Entity, value: Self::Component);
 
-    fn flush_replace(&mut self, archetype: ArchetypeId, index: usize) -> bool;
-    fn flush_push(&mut self, archetype: ArchetypeId) -> Option<usize>;
+    /// Replaces the existing component at the given location with the staged
+    /// value of the entity. Returns `false`, when there is no existing
+    /// component; the value stays staged in this case.
+    fn flush_replace(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool;
+
+    /// Pushes the staged value of the entity to the end of the given
+    /// archetype, and returns its index.
+    fn flush_push(&mut self, entity: Entity, archetype: ArchetypeId) -> Option<usize>;
 
     fn swap_remove_and_insert(
         &mut self,
@@ -53,6 +144,24 @@ pub trait Storage: Send + Sync + Any + FromResourcesMut {
         insert_to_archetype: ArchetypeId,
     ) -> Option<usize>;
 
+    /// Like [`swap_remove_and_insert`](Self::swap_remove_and_insert), but
+    /// moves the components of multiple entities of the same archetype.
+    ///
+    /// `remove_from_indices` must be sorted in descending order. The
+    /// components are moved in the same order as by calling
+    /// `swap_remove_and_insert` for every index.
+    #[inline]
+    fn swap_remove_and_insert_many(
+        &mut self,
+        remove_from_archetype: ArchetypeId,
+        remove_from_indices: &[usize],
+        insert_to_archetype: ArchetypeId,
+    ) {
+        for &index in remove_from_indices {
+            self.swap_remove_and_insert(remove_from_archetype, index, insert_to_archetype);
+        }
+    }
+
     fn get(&self, entity: Entity, archetype: ArchetypeId, index: usize)
         -> Option<&Self::Component>;
 
@@ -62,15 +171,66 @@ pub trait Storage: Send + Sync + Any + FromResourcesMut {
         archetype: ArchetypeId,
         index: usize,
     ) -> Option<&mut Self::Component>;
+
+    /// Returns a pinned exclusive reference to the component of the given
+    /// entity.
+    ///
+    /// Only storages that never move their components (see
+    /// [`PINNED`](Self::PINNED)) return `Some`.
+    #[inline]
+    fn get_pin_mut(
+        &mut self,
+        _entity: Entity,
+        _archetype: ArchetypeId,
+        _index: usize,
+    ) -> Option<Pin<&mut Self::Component>> {
+        None
+    }
+
+    /// Returns a pointer to the components of the given archetype, and their
+    /// number.
+    ///
+    /// Only storages that can be written through a shared borrow (see
+    /// [`DISJOINT`](Self::DISJOINT)) return `Some`. A component may only be
+    /// written through the pointer, while no other reference to it exists.
+    #[inline]
+    fn column_ptr(&self, _archetype: ArchetypeId) -> Option<(NonNull<Self::Component>, usize)> {
+        None
+    }
+
+    /// Returns the number of stored components, when this is a sparse storage.
+    ///
+    /// Used by queries for choosing the smallest set of entities to iterate.
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// Appends all entities with a component in this sparse storage to `entities`.
+    #[inline]
+    fn collect_sparse_entities(&self, _entities: &mut Vec<Entity>) {}
+}
+
+/// A [`Storage`] that keeps the components of an archetype in a contiguous
+/// column.
+pub trait ColumnStorage: Storage {
+    /// Returns the components of all entities in the given archetype, in the
+    /// same order as [`Archetype::entities`].
+    fn column(&self, archetype: ArchetypeId) -> &[Self::Component];
+
+    /// Returns the components of all entities in the given archetype, in the
+    /// same order as [`Archetype::entities`].
+    fn column_mut(&mut self, archetype: ArchetypeId) -> &mut [Self::Component];
 }
 
 pub trait AnyStorage: Send + Sync + Any {
     fn component_type_id(&self) -> TypeId;
     fn contains(&self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool;
     fn swap_remove(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool;
+    fn despawn(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool;
 
-    fn flush_replace(&mut self, archetype: ArchetypeId, index: usize) -> bool;
-    fn flush_push(&mut self, archetype: ArchetypeId) -> Option<usize>;
+    fn flush_replace(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool;
+    fn flush_push(&mut self, entity: Entity, archetype: ArchetypeId) -> Option<usize>;
 
     fn swap_remove_and_insert(
         &mut self,
@@ -78,13 +238,21 @@ pub trait AnyStorage: Send + Sync + Any {
         remove_from_index: usize,
         insert_to_archetype: ArchetypeId,
     ) -> Option<usize>;
+
+    fn swap_remove_and_insert_many(
+        &mut self,
+        remove_from_archetype: ArchetypeId,
+        remove_from_indices: &[usize],
+        insert_to_archetype: ArchetypeId,
+    );
 }
 
 impl_any_cast!(dyn AnyStorage);
 
 pub struct ArchetypeStorage<T> {
-    data: Vec<Vec<T>>,
-    tmp: Option<T>,
+    data: Vec<Vec<ColumnCell<T>>>,
+    /// values that are inserted, but not yet moved into their archetype
+    staged: SparseSecondaryMap<Entity, T>,
 }
 
 pub type SlotStorage<T> = SecondaryMap<Entity, T>;
@@ -95,17 +263,64 @@ pub type DenseStorage<T> = ArchetypeStorage<T>;
 #[deprecated]
 pub type HashMapStorage<T> = SparseStorage<T>;
 
+/// A component in a column of an [`ArchetypeStorage`].
+///
+/// Components are written through [`Storage::column_ptr`] while the storage
+/// is only borrowed shared, so they are kept in an `UnsafeCell`.
+#[repr(transparent)]
+struct ColumnCell<T>(UnsafeCell<T>);
+
+// SAFETY: components are only written through a shared borrow of the storage,
+// when no other reference to them exists (see `Storage::column_ptr`)
+unsafe impl<T: Sync> Sync for ColumnCell<T> {}
+
+impl<T> ColumnCell<T> {
+    #[inline]
+    fn new(value: T) -> Self {
+        Self(UnsafeCell::new(value))
+    }
+
+    #[inline]
+    fn as_slice(cells: &[Self]) -> &[T] {
+        // SAFETY: `ColumnCell<T>` has the same layout as `T`, and components
+        // are not written while they are borrowed (see `Storage::column_ptr`)
+        unsafe { std::slice::from_raw_parts(cells.as_ptr().cast::<T>(), cells.len()) }
+    }
+
+    #[inline]
+    fn as_mut_slice(cells: &mut [Self]) -> &mut [T] {
+        // SAFETY: `ColumnCell<T>` has the same layout as `T`
+        unsafe { std::slice::from_raw_parts_mut(cells.as_mut_ptr().cast::<T>(), cells.len()) }
+    }
+}
+
 impl<T> Default for ArchetypeStorage<T> {
     #[inline]
     fn default() -> Self {
         Self {
             data: Vec::new(),
-            tmp: None,
+            staged: SparseSecondaryMap::new(),
+        }
+    }
+}
+
+/// Moves the items at the given indices (sorted in descending order) from
+/// `from` to the end of `to`, in the same order as calling `swap_remove` and
+/// `push` for every index. When all items are moved, the whole vector is
+/// moved at once.
+pub(crate) fn swap_remove_and_push_many<T>(from: &mut Vec<T>, indices: &[usize], to: &mut Vec<T>) {
+    debug_assert!(indices.windows(2).all(|w| w[0] > w[1]));
+    if indices.len() == from.len() {
+        to.extend(from.drain(..).rev());
+    } else {
+        to.reserve(indices.len());
+        for &index in indices {
+            to.push(from.swap_remove(index));
         }
     }
 }
 
-fn vec_make_available<T: Default>(vec: &mut Vec<T>, index: usize) -> &mut T {
+pub(crate) fn vec_make_available<T: Default>(vec: &mut Vec<T>, index: usize) -> &mut T {
     if vec.len() <= index {
         vec.resize_with(index + 1, Default::default);
     }
@@ -118,6 +333,8 @@ where
     T: Send + Sync + 'static,
 {
     const SPARSE: bool = false;
+    const TAG: bool = std::mem::size_of::<T>() == 0 && !std::mem::needs_drop::<T>();
+    const DISJOINT: bool = true;
     type Component = T;
 
     #[inline]
@@ -138,44 +355,41 @@ where
     }
 
     #[inline]
-    fn swap_remove(&mut self, _entity: Entity, archetype: ArchetypeId, index: usize) -> Option<T> {
-        self.tmp = None;
+    fn swap_remove(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> Option<T> {
+        self.staged.remove(entity);
         if let Some(col) = self.data.get_mut(archetype.index()) {
             if index < col.len() {
-                return Some(col.swap_remove(index));
+                return Some(col.swap_remove(index).0.into_inner());
             }
         }
         None
     }
 
     #[inline]
-    fn insert(&mut self, _entity: Entity, value: T) {
-        self.tmp.replace(value);
+    fn insert(&mut self, entity: Entity, value: T) {
+        self.staged.insert(entity, value);
     }
 
-    fn flush_replace(&mut self, archetype: ArchetypeId, index: usize) -> bool {
-        let Some(cell) = self
+    fn flush_replace(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        let exists = self
             .data
-            .get_mut(archetype.index())
-            .and_then(|c| c.get_mut(index))
-        else {
+            .get(archetype.index())
+            .map_or(false, |c| index < c.len());
+        if !exists {
             return false;
-        };
-        if let Some(value) = self.tmp.take() {
-            *cell = value;
-            true
-        } else {
-            false
         }
+        let Some(value) = self.staged.remove(entity) else {
+            return false;
+        };
+        *self.data[archetype.index()][index].0.get_mut() = value;
+        true
     }
 
-    fn flush_push(&mut self, archetype: ArchetypeId) -> Option<usize> {
-        let Some(value) = self.tmp.take() else {
-            return None;
-        };
+    fn flush_push(&mut self, entity: Entity, archetype: ArchetypeId) -> Option<usize> {
+        let value = self.staged.remove(entity)?;
         let col = vec_make_available(&mut self.data, archetype.index());
         let index = col.len();
-        col.push(value);
+        col.push(ColumnCell::new(value));
         Some(index)
     }
 
@@ -188,9 +402,7 @@ where
         if remove_from_archetype == insert_to_archetype {
             return None;
         }
-        let Some(col) = self.data.get_mut(remove_from_archetype.index()) else {
-            return None;
-        };
+        let col = self.data.get_mut(remove_from_archetype.index())?;
         if remove_from_index >= col.len() {
             return None;
         }
@@ -201,6 +413,24 @@ where
         Some(index)
     }
 
+    fn swap_remove_and_insert_many(
+        &mut self,
+        remove_from_archetype: ArchetypeId,
+        remove_from_indices: &[usize],
+        insert_to_archetype: ArchetypeId,
+    ) {
+        if remove_from_archetype == insert_to_archetype {
+            return;
+        }
+        let Some(col) = self.data.get_mut(remove_from_archetype.index()) else {
+            return;
+        };
+        let mut from = std::mem::take(col);
+        let to = vec_make_available(&mut self.data, insert_to_archetype.index());
+        swap_remove_and_push_many(&mut from, remove_from_indices, to);
+        self.data[remove_from_archetype.index()] = from;
+    }
+
     #[inline]
     fn get(
         &self,
@@ -208,7 +438,7 @@ where
         archetype: ArchetypeId,
         index: usize,
     ) -> Option<&Self::Component> {
-        self.data.get(archetype.index())?.get(index)
+        ColumnCell::as_slice(self.data.get(archetype.index())?).get(index)
     }
 
     #[inline]
@@ -218,7 +448,39 @@ where
         archetype: ArchetypeId,
         index: usize,
     ) -> Option<&mut Self::Component> {
-        self.data.get_mut(archetype.index())?.get_mut(index)
+        Some(
+            self.data
+                .get_mut(archetype.index())?
+                .get_mut(index)?
+                .0
+                .get_mut(),
+        )
+    }
+
+    #[inline]
+    fn column_ptr(&self, archetype: ArchetypeId) -> Option<(NonNull<T>, usize)> {
+        let col = self.data.get(archetype.index())?;
+        let ptr = UnsafeCell::raw_get(col.as_ptr().cast::<UnsafeCell<T>>());
+        Some((NonNull::new(ptr)?, col.len()))
+    }
+}
+
+impl<T> ColumnStorage for ArchetypeStorage<T>
+where
+    T: Send + Sync + 'static,
+{
+    #[inline]
+    fn column(&self, archetype: ArchetypeId) -> &[T] {
+        self.data
+            .get(archetype.index())
+            .map_or(&[], |col| ColumnCell::as_slice(col))
+    }
+
+    #[inline]
+    fn column_mut(&mut self, archetype: ArchetypeId) -> &mut [T] {
+        self.data
+            .get_mut(archetype.index())
+            .map_or(&mut [], |col| ColumnCell::as_mut_slice(col))
     }
 }
 
@@ -255,12 +517,12 @@ where
     }
 
     #[inline]
-    fn flush_replace(&mut self, _archetype: ArchetypeId, _index: usize) -> bool {
+    fn flush_replace(&mut self, _entity: Entity, _archetype: ArchetypeId, _index: usize) -> bool {
         true
     }
 
     #[inline]
-    fn flush_push(&mut self, _archetype: ArchetypeId) -> Option<usize> {
+    fn flush_push(&mut self, _entity: Entity, _archetype: ArchetypeId) -> Option<usize> {
         None
     }
 
@@ -293,6 +555,214 @@ where
     ) -> Option<&mut Self::Component> {
         self.get_mut(entity)
     }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        Some(self.len())
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        entities.extend(self.keys());
+    }
+}
+
+/// The number of components in a chunk of a [`PinnedStorage`].
+const PINNED_CHUNK_SIZE: usize = 64;
+
+/// A sparse storage, that never moves its components.
+///
+/// Components are allocated in chunks of a fixed size, and the slots of
+/// removed components are reused. The address of a component stays the same,
+/// until it is removed or replaced, so pointers to it can be passed to FFI.
+///
+/// Components can't be borrowed as `&mut T`, they are accessed with
+/// `Pin<&mut T>` instead (in queries, or with
+/// [`EntityMut::get_pin_mut`](crate::entity::EntityMut::get_pin_mut)).
+/// Removed components are dropped in place, so
+/// [`swap_remove`](Storage::swap_remove) always returns `None` (see
+/// [`swap_remove_and_drop`](Storage::swap_remove_and_drop)).
+pub struct PinnedStorage<T> {
+    chunks: Vec<Box<[Option<T>]>>,
+    slots: SparseSecondaryMap<Entity, usize>,
+    free: Vec<usize>,
+    next: usize,
+}
+
+impl<T> Default for PinnedStorage<T> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            chunks: Vec::new(),
+            slots: SparseSecondaryMap::new(),
+            free: Vec::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<T> PinnedStorage<T> {
+    /// Returns the number of stored components.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    #[inline]
+    fn slot(&self, slot: usize) -> &Option<T> {
+        &self.chunks[slot / PINNED_CHUNK_SIZE][slot % PINNED_CHUNK_SIZE]
+    }
+
+    #[inline]
+    fn slot_mut(&mut self, slot: usize) -> &mut Option<T> {
+        &mut self.chunks[slot / PINNED_CHUNK_SIZE][slot % PINNED_CHUNK_SIZE]
+    }
+
+    fn alloc(&mut self) -> usize {
+        if let Some(slot) = self.free.pop() {
+            return slot;
+        }
+        let slot = self.next;
+        self.next += 1;
+        if slot / PINNED_CHUNK_SIZE == self.chunks.len() {
+            self.chunks
+                .push((0..PINNED_CHUNK_SIZE).map(|_| None).collect());
+        }
+        slot
+    }
+}
+
+impl<T> Storage for PinnedStorage<T>
+where
+    T: Send + Sync + 'static,
+{
+    const SPARSE: bool = true;
+    const MUTABLE: bool = false;
+    const PINNED: bool = true;
+    type Component = T;
+
+    #[inline]
+    fn fast_contains(
+        res: &Resources,
+        entity: Entity,
+        component: &ComponentDetails,
+        _archetype: &Archetype,
+    ) -> bool {
+        res.borrow_res_id(component.storage_id.typed::<Self>())
+            .map_or(false, |s| s.slots.contains_key(entity))
+    }
+
+    #[inline]
+    fn contains(&self, entity: Entity, _archetype: ArchetypeId, _index: usize) -> bool {
+        self.slots.contains_key(entity)
+    }
+
+    /// Drops the component in place, and always returns `None`.
+    #[inline]
+    fn swap_remove(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> Option<T> {
+        self.swap_remove_and_drop(entity, archetype, index);
+        None
+    }
+
+    fn swap_remove_and_drop(
+        &mut self,
+        entity: Entity,
+        _archetype: ArchetypeId,
+        _index: usize,
+    ) -> bool {
+        let Some(slot) = self.slots.remove(entity) else {
+            return false;
+        };
+        // dropped in place, the pinned value is never moved out
+        *self.slot_mut(slot) = None;
+        self.free.push(slot);
+        true
+    }
+
+    #[inline]
+    fn despawn_and_drop(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        self.swap_remove_and_drop(entity, archetype, index)
+    }
+
+    fn insert(&mut self, entity: Entity, value: T) {
+        let slot = match self.slots.get(entity) {
+            Some(&slot) => slot,
+            None => {
+                let slot = self.alloc();
+                self.slots.insert(entity, slot);
+                slot
+            }
+        };
+        // an existing value is dropped in place
+        *self.slot_mut(slot) = Some(value);
+    }
+
+    #[inline]
+    fn flush_replace(&mut self, _entity: Entity, _archetype: ArchetypeId, _index: usize) -> bool {
+        true
+    }
+
+    #[inline]
+    fn flush_push(&mut self, _entity: Entity, _archetype: ArchetypeId) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn swap_remove_and_insert(
+        &mut self,
+        _remove_from_archetype: ArchetypeId,
+        _remove_from_index: usize,
+        _insert_to_archetype: ArchetypeId,
+    ) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn get(&self, entity: Entity, _archetype: ArchetypeId, _index: usize) -> Option<&T> {
+        let &slot = self.slots.get(entity)?;
+        self.slot(slot).as_ref()
+    }
+
+    /// Pinned components can't be borrowed as `&mut T`, because they could
+    /// be moved out of the storage (see [`get_pin_mut`](Self::get_pin_mut)).
+    #[inline]
+    fn get_mut(
+        &mut self,
+        _entity: Entity,
+        _archetype: ArchetypeId,
+        _index: usize,
+    ) -> Option<&mut T> {
+        None
+    }
+
+    #[inline]
+    fn get_pin_mut(
+        &mut self,
+        entity: Entity,
+        _archetype: ArchetypeId,
+        _index: usize,
+    ) -> Option<Pin<&mut T>> {
+        let &slot = self.slots.get(entity)?;
+        let value = self.slot_mut(slot).as_mut()?;
+        // SAFETY: the chunks are never reallocated, components are only
+        // dropped in place, and never borrowed as `&mut T`.
+        Some(unsafe { Pin::new_unchecked(value) })
+    }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        Some(self.slots.len())
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        entities.extend(self.slots.keys());
+    }
 }
 
 pub struct Tracked<S> {
@@ -318,6 +788,10 @@ impl<S: FromResourcesMut> FromResourcesMut for Tracked<S> {
 
 impl<S: Storage> Storage for Tracked<S> {
     const SPARSE: bool = S::SPARSE;
+    const MUTABLE: bool = S::MUTABLE;
+    const BORROWABLE: bool = S::BORROWABLE;
+    const PINNED: bool = S::PINNED;
+    const DISJOINT: bool = S::DISJOINT;
     type Component = S::Component;
 
     fn install_systems(schedule: &mut Schedule) {
@@ -353,19 +827,54 @@ impl<S: Storage> Storage for Tracked<S> {
         Some(old)
     }
 
+    #[inline]
+    fn despawn(
+        &mut self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        index: usize,
+    ) -> Option<Self::Component> {
+        let old = self.base.despawn(entity, archetype, index)?;
+        insert_sorted(&mut self.removed, entity);
+        Some(old)
+    }
+
+    #[inline]
+    fn swap_remove_and_drop(
+        &mut self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        index: usize,
+    ) -> bool {
+        let removed = self.base.swap_remove_and_drop(entity, archetype, index);
+        if removed {
+            insert_sorted(&mut self.removed, entity);
+        }
+        removed
+    }
+
+    #[inline]
+    fn despawn_and_drop(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        let removed = self.base.despawn_and_drop(entity, archetype, index);
+        if removed {
+            insert_sorted(&mut self.removed, entity);
+        }
+        removed
+    }
+
     #[inline]
     fn insert(&mut self, entity: Entity, value: Self::Component) {
         self.base.insert(entity, value)
     }
 
     #[inline]
-    fn flush_replace(&mut self, archetype: ArchetypeId, index: usize) -> bool {
-        self.base.flush_replace(archetype, index)
+    fn flush_replace(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        self.base.flush_replace(entity, archetype, index)
     }
 
     #[inline]
-    fn flush_push(&mut self, archetype: ArchetypeId) -> Option<usize> {
-        self.base.flush_push(archetype)
+    fn flush_push(&mut self, entity: Entity, archetype: ArchetypeId) -> Option<usize> {
+        self.base.flush_push(entity, archetype)
     }
 
     #[inline]
@@ -382,6 +891,20 @@ impl<S: Storage> Storage for Tracked<S> {
         )
     }
 
+    #[inline]
+    fn swap_remove_and_insert_many(
+        &mut self,
+        remove_from_archetype: ArchetypeId,
+        remove_from_indices: &[usize],
+        insert_to_archetype: ArchetypeId,
+    ) {
+        self.base.swap_remove_and_insert_many(
+            remove_from_archetype,
+            remove_from_indices,
+            insert_to_archetype,
+        )
+    }
+
     #[inline]
     fn get(
         &self,
@@ -401,6 +924,299 @@ impl<S: Storage> Storage for Tracked<S> {
     ) -> Option<&mut Self::Component> {
         self.base.get_mut(entity, archetype, index)
     }
+
+    #[inline]
+    fn get_pin_mut(
+        &mut self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        index: usize,
+    ) -> Option<Pin<&mut Self::Component>> {
+        self.base.get_pin_mut(entity, archetype, index)
+    }
+
+    #[inline]
+    fn column_ptr(&self, archetype: ArchetypeId) -> Option<(NonNull<Self::Component>, usize)> {
+        self.base.column_ptr(archetype)
+    }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        self.base.sparse_len()
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        self.base.collect_sparse_entities(entities)
+    }
+}
+
+impl<S: ColumnStorage> ColumnStorage for Tracked<S> {
+    #[inline]
+    fn column(&self, archetype: ArchetypeId) -> &[Self::Component] {
+        self.base.column(archetype)
+    }
+
+    #[inline]
+    fn column_mut(&mut self, archetype: ArchetypeId) -> &mut [Self::Component] {
+        self.base.column_mut(archetype)
+    }
+}
+
+impl<S: Indexed> Indexed for Tracked<S> {
+    #[inline]
+    fn index(&self) -> &IndexedStorage<Self::Component> {
+        self.base.index()
+    }
+}
+
+/// A storage for immutable components, that can only be changed by
+/// replacing them with [`Storage::insert`].
+///
+/// Components in this storage can't be borrowed mutably: queries for
+/// `&mut T` are rejected when they are initialized, and `get_mut` returns
+/// `None`. This keeps values like stable ids or index keys consistent with
+/// the storages and indices that observe their insertion.
+///
+/// There are no component hooks, so replacing a component doesn't notify
+/// anything besides the storage itself. Use [`Tracked`] to observe removals.
+pub struct Immutable<S> {
+    base: S,
+}
+
+impl<S: FromResourcesMut> FromResourcesMut for Immutable<S> {
+    #[inline]
+    fn from_resources_mut(resources: &mut Resources) -> Self {
+        Self {
+            base: S::from_resources_mut(resources),
+        }
+    }
+}
+
+impl<S: Storage> Storage for Immutable<S> {
+    const SPARSE: bool = S::SPARSE;
+    const MUTABLE: bool = false;
+    const BORROWABLE: bool = S::BORROWABLE;
+    const PINNED: bool = S::PINNED;
+    type Component = S::Component;
+
+    #[inline]
+    fn install_systems(schedule: &mut Schedule) {
+        S::install_systems(schedule)
+    }
+
+    #[inline]
+    fn fast_contains(
+        res: &Resources,
+        entity: Entity,
+        component: &ComponentDetails,
+        archetype: &Archetype,
+    ) -> bool {
+        S::fast_contains(res, entity, component, archetype)
+    }
+
+    #[inline]
+    fn contains(&self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        self.base.contains(entity, archetype, index)
+    }
+
+    #[inline]
+    fn swap_remove(
+        &mut self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        index: usize,
+    ) -> Option<Self::Component> {
+        self.base.swap_remove(entity, archetype, index)
+    }
+
+    #[inline]
+    fn despawn(
+        &mut self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        index: usize,
+    ) -> Option<Self::Component> {
+        self.base.despawn(entity, archetype, index)
+    }
+
+    #[inline]
+    fn swap_remove_and_drop(
+        &mut self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        index: usize,
+    ) -> bool {
+        self.base.swap_remove_and_drop(entity, archetype, index)
+    }
+
+    #[inline]
+    fn despawn_and_drop(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        self.base.despawn_and_drop(entity, archetype, index)
+    }
+
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: Self::Component) {
+        self.base.insert(entity, value)
+    }
+
+    #[inline]
+    fn flush_replace(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        self.base.flush_replace(entity, archetype, index)
+    }
+
+    #[inline]
+    fn flush_push(&mut self, entity: Entity, archetype: ArchetypeId) -> Option<usize> {
+        self.base.flush_push(entity, archetype)
+    }
+
+    #[inline]
+    fn swap_remove_and_insert(
+        &mut self,
+        remove_from_archetype: ArchetypeId,
+        remove_from_index: usize,
+        insert_to_archetype: ArchetypeId,
+    ) -> Option<usize> {
+        self.base.swap_remove_and_insert(
+            remove_from_archetype,
+            remove_from_index,
+            insert_to_archetype,
+        )
+    }
+
+    #[inline]
+    fn swap_remove_and_insert_many(
+        &mut self,
+        remove_from_archetype: ArchetypeId,
+        remove_from_indices: &[usize],
+        insert_to_archetype: ArchetypeId,
+    ) {
+        self.base.swap_remove_and_insert_many(
+            remove_from_archetype,
+            remove_from_indices,
+            insert_to_archetype,
+        )
+    }
+
+    #[inline]
+    fn get(
+        &self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        index: usize,
+    ) -> Option<&Self::Component> {
+        self.base.get(entity, archetype, index)
+    }
+
+    /// Immutable components can't be borrowed mutably.
+    #[inline]
+    fn get_mut(
+        &mut self,
+        _entity: Entity,
+        _archetype: ArchetypeId,
+        _index: usize,
+    ) -> Option<&mut Self::Component> {
+        None
+    }
+
+    #[inline]
+    fn get_pin_mut(
+        &mut self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        index: usize,
+    ) -> Option<Pin<&mut Self::Component>> {
+        self.base.get_pin_mut(entity, archetype, index)
+    }
+
+    #[inline]
+    fn sparse_len(&self) -> Option<usize> {
+        self.base.sparse_len()
+    }
+
+    #[inline]
+    fn collect_sparse_entities(&self, entities: &mut Vec<Entity>) {
+        self.base.collect_sparse_entities(entities)
+    }
+}
+
+impl<S: Indexed> Indexed for Immutable<S> {
+    #[inline]
+    fn index(&self) -> &IndexedStorage<Self::Component> {
+        self.base.index()
+    }
+}
+
+/// Returns a reference to a tag component (see [`Storage::TAG`]).
+///
+/// # Safety
+/// `T` must be zero-sized, and a value of `T` must exist (e.g. it was
+/// inserted into an entity).
+#[inline(always)]
+pub(crate) unsafe fn tag_ref<'a, T>() -> &'a T {
+    debug_assert_eq!(0, std::mem::size_of::<T>());
+    NonNull::dangling().as_ref()
+}
+
+/// Returns an exclusive reference to a tag component (see [`Storage::TAG`]).
+///
+/// # Safety
+/// See [`tag_ref`].
+#[inline(always)]
+pub(crate) unsafe fn tag_mut<'a, T>() -> &'a mut T {
+    debug_assert_eq!(0, std::mem::size_of::<T>());
+    NonNull::dangling().as_mut()
+}
+
+/// Returns a column of `len` tag components (see [`Storage::TAG`]).
+///
+/// # Safety
+/// See [`tag_ref`].
+#[inline(always)]
+pub(crate) unsafe fn tag_column_mut<'a, T>(len: usize) -> &'a mut [T] {
+    debug_assert_eq!(0, std::mem::size_of::<T>());
+    std::slice::from_raw_parts_mut(NonNull::dangling().as_ptr(), len)
+}
+
+/// Returns the component of the entity at the given location. Tags (see
+/// [`Storage::TAG`]) are looked up in the component set of the archetype.
+#[inline]
+pub(crate) fn get_component<'a, S: Storage>(
+    storage: &'a S,
+    component: &ComponentDetails,
+    entity: Entity,
+    archetype: &Archetype,
+    index: usize,
+) -> Option<&'a S::Component> {
+    if S::TAG {
+        // SAFETY: the tag was inserted, when the archetype contains it
+        archetype
+            .components
+            .contains(component.id())
+            .then(|| unsafe { tag_ref() })
+    } else {
+        storage.get(entity, archetype.id, index)
+    }
+}
+
+/// Like [`get_component`], but returns an exclusive reference.
+#[inline]
+pub(crate) fn get_component_mut<'a, S: Storage>(
+    storage: &'a mut S,
+    component: &ComponentDetails,
+    entity: Entity,
+    archetype: &Archetype,
+    index: usize,
+) -> Option<&'a mut S::Component> {
+    if S::TAG {
+        // SAFETY: the tag was inserted, when the archetype contains it
+        archetype
+            .components
+            .contains(component.id())
+            .then(|| unsafe { tag_mut() })
+    } else {
+        storage.get_mut(entity, archetype.id, index)
+    }
 }
 
 impl<S> AnyStorage for S
@@ -416,15 +1232,19 @@ where
     }
 
     fn swap_remove(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
-        S::swap_remove(self, entity, archetype, index).is_some()
+        S::swap_remove_and_drop(self, entity, archetype, index)
+    }
+
+    fn despawn(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        S::despawn_and_drop(self, entity, archetype, index)
     }
 
-    fn flush_replace(&mut self, archetype: ArchetypeId, index: usize) -> bool {
-        S::flush_replace(self, archetype, index)
+    fn flush_replace(&mut self, entity: Entity, archetype: ArchetypeId, index: usize) -> bool {
+        S::flush_replace(self, entity, archetype, index)
     }
 
-    fn flush_push(&mut self, archetype: ArchetypeId) -> Option<usize> {
-        S::flush_push(self, archetype)
+    fn flush_push(&mut self, entity: Entity, archetype: ArchetypeId) -> Option<usize> {
+        S::flush_push(self, entity, archetype)
     }
 
     fn swap_remove_and_insert(
@@ -440,4 +1260,339 @@ where
             insert_to_archetype,
         )
     }
+
+    fn swap_remove_and_insert_many(
+        &mut self,
+        remove_from_archetype: ArchetypeId,
+        remove_from_indices: &[usize],
+        insert_to_archetype: ArchetypeId,
+    ) {
+        S::swap_remove_and_insert_many(
+            self,
+            remove_from_archetype,
+            remove_from_indices,
+            insert_to_archetype,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pulz_schedule::resource::Resources;
+
+    use super::{ArchetypeStorage, ColumnStorage, PinnedStorage, Storage, Tracked};
+    use crate::{archetype::ArchetypeId, component::Component, query::Query, WorldExt};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    struct A(usize);
+
+    #[test]
+    fn test_archetype_storage_staging() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let [e1, e2, e3] = [(); 3].map(|_| world.spawn().id());
+        let archetype = ArchetypeId::new(1);
+        let mut storage = ArchetypeStorage::<usize>::default();
+
+        // multiple staged values
+        storage.insert(e1, 1);
+        storage.insert(e2, 2);
+        assert_eq!(Some(0), storage.flush_push(e2, archetype));
+        assert_eq!(Some(1), storage.flush_push(e1, archetype));
+        assert_eq!(&[2, 1], storage.column(archetype));
+
+        // removing another entity keeps the staged value
+        storage.insert(e1, 10);
+        assert_eq!(Some(2), storage.swap_remove(e2, archetype, 0));
+        assert!(storage.flush_replace(e1, archetype, 0));
+        assert_eq!(&[10], storage.column(archetype));
+
+        // insert, remove, insert
+        storage.insert(e3, 3);
+        assert_eq!(None, storage.swap_remove(e3, archetype, 1));
+        assert_eq!(None, storage.flush_push(e3, archetype));
+        storage.insert(e3, 4);
+        storage.insert(e3, 5);
+        assert!(!storage.flush_replace(e3, archetype, 1));
+        assert_eq!(Some(1), storage.flush_push(e3, archetype));
+        assert_eq!(&[10, 5], storage.column(archetype));
+
+        // staging reuses the buffer
+        let capacity = storage.staged.capacity();
+        for i in 0..100 {
+            storage.insert(e1, i);
+            storage.insert(e2, i);
+            assert!(storage.flush_replace(e1, archetype, 0));
+            assert!(storage.flush_replace(e2, archetype, 1));
+        }
+        assert_eq!(capacity, storage.staged.capacity());
+        assert_eq!(&[99, 99], storage.column(archetype));
+    }
+
+    #[test]
+    fn test_insert_remove_insert() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let mut entity = world.spawn();
+        entity.insert(A(1)).remove::<A>().insert(A(2));
+        let id = entity.id();
+        drop(entity);
+        let entity = world.entity(id).unwrap();
+        assert_eq!(Some(A(2)), entity.borrow::<A>().as_deref().copied());
+
+        let mut entity = world.entity_mut(id).unwrap();
+        entity.insert(A(3)).remove::<A>();
+        drop(entity);
+        assert!(!world.entity(id).unwrap().contains::<A>());
+
+        let mut entity = world.entity_mut(id).unwrap();
+        entity.insert(A(4)).insert(A(5));
+        drop(entity);
+        assert_eq!(
+            Some(A(5)),
+            world.entity(id).unwrap().borrow::<A>().as_deref().copied()
+        );
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(immutable)]
+    struct Key(usize);
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(sparse, immutable, tracked)]
+    struct SparseKey(usize);
+
+    #[test]
+    fn test_immutable_replace() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let e = world.spawn().insert(Key(1)).insert(SparseKey(2)).id();
+        world
+            .entity_mut(e)
+            .unwrap()
+            .insert(Key(3))
+            .insert(SparseKey(4));
+        world.insert_for_each([e], |_| Key(5));
+        let entity = world.entity(e).unwrap();
+        assert_eq!(Some(Key(5)), entity.borrow::<Key>().as_deref().copied());
+        assert_eq!(
+            Some(SparseKey(4)),
+            entity.borrow::<SparseKey>().as_deref().copied()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "is immutable")]
+    fn test_immutable_get_mut() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let e = world.spawn().insert(SparseKey(1)).id();
+        world.entity_mut(e).unwrap().get_mut::<SparseKey>();
+    }
+
+    #[test]
+    #[should_panic(expected = "is immutable")]
+    fn test_immutable_query() {
+        let mut resources = Resources::new();
+        resources.world_mut().spawn().insert(Key(1));
+        let _ = Query::<&mut Key>::new(&mut resources);
+    }
+
+    #[derive(Debug, Component)]
+    #[component(storage = PinnedStorage)]
+    struct Body {
+        id: usize,
+        _pin: std::marker::PhantomPinned,
+    }
+
+    impl Body {
+        fn new(id: usize) -> Self {
+            Self {
+                id,
+                _pin: std::marker::PhantomPinned,
+            }
+        }
+
+        fn set_id(self: std::pin::Pin<&mut Self>, id: usize) {
+            // SAFETY: `id` is not structurally pinned
+            unsafe { self.get_unchecked_mut().id = id }
+        }
+    }
+
+    #[test]
+    fn test_pinned_storage() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let entities: Vec<_> = (0..100)
+            .map(|i| world.spawn().insert(Body::new(i)).id())
+            .collect();
+        let address = |world: &crate::world::WorldMut<'_>, e| {
+            let entity = world.entity(e).unwrap();
+            let body = entity.borrow::<Body>().unwrap();
+            std::ptr::addr_of!(*body)
+        };
+        let addresses: Vec<_> = entities.iter().map(|&e| address(&world, e)).collect();
+
+        // archetype changes, removals and new components don't move components
+        for &e in &entities[..50] {
+            world.entity_mut(e).unwrap().insert(A(1));
+        }
+        for &e in &entities[50..60] {
+            world.entity_mut(e).unwrap().remove::<Body>();
+        }
+        let more: Vec<_> = (100..120)
+            .map(|i| world.spawn().insert(Body::new(i)).id())
+            .collect();
+        for (&e, &a) in entities.iter().zip(&addresses) {
+            if world.entity(e).unwrap().contains::<Body>() {
+                assert_eq!(a, address(&world, e));
+            }
+        }
+        // slots are reused
+        let reused = address(&world, more[0]);
+        assert!(addresses[50..60].contains(&reused));
+
+        world
+            .entity_mut(entities[1])
+            .unwrap()
+            .get_pin_mut::<Body>()
+            .unwrap()
+            .set_id(1000);
+        assert!(world
+            .entity_mut(entities[50])
+            .unwrap()
+            .get_pin_mut::<Body>()
+            .is_none());
+        drop(world);
+
+        let mut query = Query::<std::pin::Pin<&mut Body>>::new(&mut resources);
+        let mut count = 0;
+        for body in query.iter() {
+            body.set_id(count);
+            count += 1;
+        }
+        assert_eq!(110, count);
+    }
+
+    #[derive(Debug, Component)]
+    #[component(tracked, storage = PinnedStorage)]
+    struct TrackedBody(usize);
+
+    #[test]
+    fn test_pinned_storage_tracks_removed() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let a = world.spawn().insert(TrackedBody(1)).id();
+        let b = world.spawn().insert(TrackedBody(2)).id();
+        let c = world.spawn().insert(TrackedBody(3)).id();
+        world.entity_mut(a).unwrap().remove::<TrackedBody>();
+        world.despawn(b);
+        drop(world);
+
+        let storage = resources
+            .borrow_res::<Tracked<PinnedStorage<TrackedBody>>>()
+            .unwrap();
+        assert_eq!(vec![a, b], storage.removed);
+        assert!(!storage.contains(a, ArchetypeId::EMPTY, 0));
+        assert_eq!(3, storage.get(c, ArchetypeId::EMPTY, 0).unwrap().0);
+    }
+
+    #[derive(Debug, Component)]
+    #[component(immutable, storage = PinnedStorage)]
+    struct ImmutableBody(Body);
+
+    #[test]
+    fn test_immutable_pinned_storage() {
+        let mut resources = Resources::new();
+        let e = resources
+            .world_mut()
+            .spawn()
+            .insert(ImmutableBody(Body::new(1)))
+            .id();
+        let mut query = Query::<std::pin::Pin<&mut ImmutableBody>>::new(&mut resources);
+        let ids: Vec<usize> = query.iter().map(|body| body.0.id).collect();
+        assert_eq!(vec![1], ids);
+        drop(query);
+        let mut world = resources.world_mut();
+        assert!(world
+            .entity_mut(e)
+            .unwrap()
+            .get_pin_mut::<ImmutableBody>()
+            .is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "is not pinned")]
+    fn test_pin_query_requires_pinned_storage() {
+        let mut resources = Resources::new();
+        resources.world_mut().spawn().insert(A(1));
+        let _ = Query::<std::pin::Pin<&mut A>>::new(&mut resources);
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Component)]
+    #[component(clone)]
+    struct Player;
+
+    #[derive(Debug, Component)]
+    struct DropMarker;
+
+    impl Drop for DropMarker {
+        fn drop(&mut self) {}
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut resources = Resources::new();
+        let mut world = resources.world_mut();
+        let a = world.spawn().insert(A(1)).insert(Player).id();
+        let b = world.spawn().insert(A(2)).id();
+        let c = world.spawn().insert(Player).insert(DropMarker).id();
+        world.insert_for_each([b], |_| Player);
+        world.entity_mut(a).unwrap().insert(Player).remove::<A>();
+        world.entity_mut(b).unwrap().remove::<Player>();
+        let d = world.clone_entity(c).unwrap();
+
+        let components = world.components();
+        assert!(components
+            .get(components.expect_id::<Player>())
+            .unwrap()
+            .is_tag());
+        assert!(!components
+            .get(components.expect_id::<A>())
+            .unwrap()
+            .is_tag());
+        assert!(!components
+            .get(components.expect_id::<DropMarker>())
+            .unwrap()
+            .is_tag());
+
+        assert!(world.entity(a).unwrap().contains::<Player>());
+        assert!(world.entity(a).unwrap().borrow::<Player>().is_some());
+        assert!(!world.entity(a).unwrap().contains::<A>());
+        assert!(!world.entity(b).unwrap().contains::<Player>());
+        assert!(world.entity(b).unwrap().borrow::<Player>().is_none());
+        assert_eq!(
+            Some(A(2)),
+            world.entity(b).unwrap().borrow::<A>().as_deref().copied()
+        );
+        assert!(world.entity(d).unwrap().contains::<Player>());
+        world.despawn(c);
+        drop(world);
+
+        // the storage of tags is never used
+        let storage = resources.borrow_res::<ArchetypeStorage<Player>>().unwrap();
+        assert!(storage.data.is_empty());
+        assert!(storage.staged.is_empty());
+        drop(storage);
+
+        let mut query = Query::<(crate::Entity, &mut Player)>::new(&mut resources);
+        let mut players: Vec<_> = query.iter().map(|(e, p)| (e, *p)).collect();
+        players.sort_unstable_by_key(|(e, _)| *e);
+        assert_eq!(vec![(a, Player), (d, Player)], players);
+        drop(query);
+        let mut query = Query::<&Player>::new(&mut resources);
+        let mut count = 0;
+        query.for_each_chunk(|players| count += players.len());
+        assert_eq!(2, count);
+    }
 }
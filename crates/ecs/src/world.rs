@@ -8,7 +8,7 @@ use crate::{
     component::{Component, ComponentId, Components},
     entity::{Entities, Entity},
     get_or_init_component,
-    query::{Query, QueryParam},
+    query::{Query, QueryParam, ReadOnlyQueryParam},
     resource::{RemovedResource, Res, Resources},
     WorldInner,
 };
@@ -33,6 +33,22 @@ impl World<'_> {
     pub fn entities(&self) -> &Entities {
         &self.world.entities
     }
+
+    /// Creates a read-only query on this world.
+    ///
+    /// A new query-state is created for every call. Use
+    /// [`QueryState`](crate::query::QueryState) to run the same query
+    /// multiple times.
+    ///
+    /// # Panics
+    /// Panics when a component of the query was never registered.
+    #[inline]
+    pub fn query<Q>(&self) -> Query<'_, Q>
+    where
+        Q: ReadOnlyQueryParam + 'static,
+    {
+        Query::from_world(self.res, Res::clone(&self.world))
+    }
 }
 
 impl Clone for World<'_> {
@@ -82,6 +98,17 @@ impl WorldMut<'_> {
         get_or_init_component::<T>(self.res, &mut self.world.components).1
     }
 
+    /// Initializes the component `T` and registers its `Clone`
+    /// implementation (see [`clone_entity`](Self::clone_entity)).
+    pub fn init_clonable<T>(&mut self) -> ComponentId<T>
+    where
+        T: Component + Clone,
+    {
+        let id = self.init::<T>();
+        self.world.components.components[id.offset()].set_clonable::<T>();
+        id
+    }
+
     /// Removes the entity and all its components from the world.
     pub fn despawn(&mut self, entity: Entity) -> bool {
         let Some(ent) = self.entity_mut(entity) else {
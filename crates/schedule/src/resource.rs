@@ -557,6 +557,62 @@ impl<T: FromResources> FromResourcesMut for T {
 pub struct ResourceAccess {
     pub(crate) shared: BitSet,
     pub(crate) exclusive: BitSet,
+    names: BTreeMap<usize, Cow<'static, str>>,
+    partition: Option<AccessPartition>,
+    partitioned: BitSet,
+    conflicts: Vec<AccessConflict>,
+}
+
+/// Restricts an access to a part of a resource.
+///
+/// Resources that are split into keyed parts (like the entities of an ecs
+/// world) can describe which parts are touched: only parts having all keys
+/// in `with` and none of the keys in `without`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessPartition {
+    pub with: BitSet,
+    pub without: BitSet,
+}
+
+impl AccessPartition {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` when no part can be matched by both partitions.
+    #[inline]
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        !self.with.is_disjoint(&other.without) || !self.without.is_disjoint(&other.with)
+    }
+
+    fn intersect(&mut self, other: &Self) {
+        self.with.retain_bitset(&other.with);
+        self.without.retain_bitset(&other.without);
+    }
+}
+
+/// Two parameters of a system that access the same resource in a
+/// conflicting way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessConflict {
+    pub resource: ResourceId,
+    /// readable name of the resource (or the part of it, like a component)
+    pub name: Cow<'static, str>,
+    /// name of the parameter that accessed the resource first
+    pub first: Cow<'static, str>,
+    /// name of the parameter that accessed the resource second
+    pub second: Cow<'static, str>,
+}
+
+impl std::fmt::Display for AccessConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` is accessed by `{}` and `{}`, and at least one of them is exclusive",
+            self.name, self.first, self.second
+        )
+    }
 }
 
 impl ResourceAccess {
@@ -565,6 +621,10 @@ impl ResourceAccess {
         Self {
             shared: BitSet::new(),
             exclusive: BitSet::new(),
+            names: BTreeMap::new(),
+            partition: None,
+            partitioned: BitSet::new(),
+            conflicts: Vec::new(),
         }
     }
     #[inline]
@@ -587,7 +647,7 @@ impl ResourceAccess {
     }
     fn _add_exclusive_checked(&mut self, index: usize) -> bool {
         if self.shared.contains(index) {
-            panic!("resource {index} is already used as exclusive");
+            panic!("resource {index} is already used as shared");
         }
         self.exclusive.insert(index)
     }
@@ -601,18 +661,162 @@ impl ResourceAccess {
     }
     #[inline]
     pub fn is_exclusive<T>(&self, resource: ResourceId<T>) -> bool {
-        self.shared.contains(resource.0)
+        self.exclusive.contains(resource.0)
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.shared.is_empty() && self.exclusive.is_empty()
+    }
+    #[inline]
+    pub fn iter_shared(&self) -> impl Iterator<Item = ResourceId> + '_ {
+        self.shared
+            .iter()
+            .map(|index| ResourceId(index, PhantomData))
+    }
+    #[inline]
+    pub fn iter_exclusive(&self) -> impl Iterator<Item = ResourceId> + '_ {
+        self.exclusive
+            .iter()
+            .map(|index| ResourceId(index, PhantomData))
+    }
+
+    /// Sets a readable name for the accessed resource, that is used in the
+    /// description of conflicts instead of the name of the resource.
+    #[inline]
+    pub fn set_name<T>(&mut self, resource: ResourceId<T>, name: impl Into<Cow<'static, str>>) {
+        self.names.insert(resource.0, name.into());
+    }
+    #[inline]
+    pub fn name<T>(&self, resource: ResourceId<T>) -> Option<&str> {
+        self.names.get(&resource.0).map(Cow::as_ref)
+    }
+
+    /// Restricts the accesses of the resources marked with
+    /// [`add_partitioned`](Self::add_partitioned) to the given partition.
+    ///
+    /// Accesses with disjoint partitions are not reported as conflicts by
+    /// [`extend_checked`](Self::extend_checked), when the resource is marked
+    /// as partitioned by both.
+    #[inline]
+    pub fn set_partition(&mut self, partition: AccessPartition) {
+        self.partition = Some(partition);
+    }
+    #[inline]
+    pub fn partition(&self) -> Option<&AccessPartition> {
+        self.partition.as_ref()
+    }
+
+    /// Marks a resource, that is split into parts, and is only accessed in
+    /// the parts of the partition (see [`set_partition`](Self::set_partition)).
+    #[inline]
+    pub fn add_partitioned<T>(&mut self, resource: ResourceId<T>) -> bool {
+        self.partitioned.insert(resource.0)
+    }
+    #[inline]
+    pub fn is_partitioned<T>(&self, resource: ResourceId<T>) -> bool {
+        self.partitioned.contains(resource.0)
+    }
+
+    #[inline]
+    pub fn add_conflict(&mut self, conflict: AccessConflict) {
+        self.conflicts.push(conflict);
+    }
+    #[inline]
+    pub fn conflicts(&self) -> &[AccessConflict] {
+        &self.conflicts
+    }
+
+    /// Returns the first resource that is accessed by both in a conflicting
+    /// way, taking partitions into account.
+    pub fn find_conflict(&self, other: &Self) -> Option<ResourceId> {
+        let mut conflicting = self.exclusive.clone();
+        conflicting.retain_bitset(&other.shared);
+        let mut tmp = self.exclusive.clone();
+        tmp.retain_bitset(&other.exclusive);
+        conflicting.extend_bitset(&tmp);
+        tmp = self.shared.clone();
+        tmp.retain_bitset(&other.exclusive);
+        conflicting.extend_bitset(&tmp);
+        if let (Some(a), Some(b)) = (&self.partition, &other.partition) {
+            if a.is_disjoint(b) {
+                // only the partitioned resources are accessed in disjoint parts
+                tmp = self.partitioned.clone();
+                tmp.retain_bitset(&other.partitioned);
+                conflicting.remove_bitset(&tmp);
+            }
+        }
+        conflicting
+            .first()
+            .map(|index| ResourceId(index, PhantomData))
     }
+
     #[inline]
     pub fn clear(&mut self) {
         self.shared.clear();
         self.exclusive.clear();
+        self.names.clear();
+        self.partition = None;
+        self.partitioned.clear();
+        self.conflicts.clear();
     }
-    #[inline]
     pub fn extend(&mut self, other: &Self) {
+        if self.is_empty() {
+            self.partition = other.partition.clone();
+        } else if !other.is_empty() {
+            match (&mut self.partition, &other.partition) {
+                (Some(a), Some(b)) => a.intersect(b),
+                (a, _) => *a = None,
+            }
+        }
         self.shared.extend_bitset(&other.shared);
         self.exclusive.extend_bitset(&other.exclusive);
+        self.partitioned.extend_bitset(&other.partitioned);
+        for (index, name) in &other.names {
+            self.names.entry(*index).or_insert_with(|| name.clone());
+        }
+        self.conflicts.extend_from_slice(&other.conflicts);
+    }
+
+    /// Extends this access with the accesses of multiple named parameters,
+    /// and records a conflict for every pair of parameters that access a
+    /// resource in a conflicting way.
+    pub fn extend_checked<I>(&mut self, resources: Option<&Resources>, params: I)
+    where
+        I: IntoIterator<Item = (Cow<'static, str>, Self)>,
+    {
+        let params: Vec<_> = params.into_iter().collect();
+        for (i, (second, b)) in params.iter().enumerate() {
+            for (first, a) in &params[..i] {
+                let Some(resource) = a.find_conflict(b) else {
+                    continue;
+                };
+                let name = a
+                    .names
+                    .get(&resource.0)
+                    .or_else(|| b.names.get(&resource.0))
+                    .cloned()
+                    .or_else(|| Some(resources?.name(resource)?.to_owned().into()))
+                    .unwrap_or_else(|| format!("resource {}", resource.0).into());
+                self.add_conflict(AccessConflict {
+                    resource,
+                    name,
+                    first: first.clone(),
+                    second: second.clone(),
+                });
+            }
+        }
+        for (_, access) in &params {
+            self.extend(access);
+        }
     }
+
+    /// Returns `true` when every access of `self` is also granted by `other`.
+    pub fn is_subset(&self, other: &Self) -> bool {
+        let mut granted = other.shared.clone();
+        granted.extend_bitset(&other.exclusive);
+        granted.contains_all(&self.shared) && other.exclusive.contains_all(&self.exclusive)
+    }
+
     #[inline]
     pub fn is_compatible(&self, other: &Self) -> bool {
         self.shared.is_disjoint(&other.exclusive)
@@ -658,6 +862,11 @@ where
     fn update_access(&self, _resources: &Resources, access: &mut ResourceAccess) {
         access.add_shared_checked(self.0);
     }
+
+    #[inline]
+    fn type_name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(std::any::type_name::<&T>())
+    }
 }
 
 impl<'r, T: 'static> SystemDataFetch<'r> for Res<'r, T> {
@@ -699,6 +908,11 @@ where
     fn update_access(&self, _resources: &Resources, access: &mut ResourceAccess) {
         access.add_exclusive_checked(self.0);
     }
+
+    #[inline]
+    fn type_name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(std::any::type_name::<&mut T>())
+    }
 }
 
 impl<'r, T: 'static> SystemDataFetch<'r> for ResMut<'r, T> {
@@ -742,6 +956,11 @@ where
             access.add_shared_checked(resource);
         }
     }
+
+    #[inline]
+    fn type_name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(std::any::type_name::<Option<&T>>())
+    }
 }
 
 impl<'r, T: 'static> SystemDataFetch<'r> for Option<Res<'r, T>> {
@@ -789,6 +1008,11 @@ where
             access.add_exclusive_checked(resource);
         }
     }
+
+    #[inline]
+    fn type_name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(std::any::type_name::<Option<&mut T>>())
+    }
 }
 
 impl<'r, T: 'static> SystemDataFetch<'r> for Option<ResMut<'r, T>> {
@@ -803,3 +1027,37 @@ impl<'r, T: 'static> SystemDataFetch<'r> for Option<ResMut<'r, T>> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_conflict_partitioned() {
+        struct Parted;
+        struct Other;
+        let mut resources = Resources::new();
+        let parted = resources.insert(Parted);
+        let other = resources.insert(Other);
+
+        let mut with = AccessPartition::new();
+        with.with.insert(0);
+        let mut without = AccessPartition::new();
+        without.without.insert(0);
+
+        let mut a = ResourceAccess::new();
+        a.add_exclusive(parted);
+        a.add_partitioned(parted);
+        a.set_partition(with);
+        let mut b = ResourceAccess::new();
+        b.add_shared(parted);
+        b.add_partitioned(parted);
+        b.set_partition(without);
+        assert_eq!(None, a.find_conflict(&b));
+
+        // resources that are not partitioned always conflict
+        a.add_exclusive(other);
+        b.add_shared(other);
+        assert_eq!(Some(other.untyped()), a.find_conflict(&b));
+    }
+}
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use crate::resource::{ResourceAccess, Resources};
 
 pub trait SystemData {
@@ -13,6 +15,16 @@ pub unsafe trait SystemDataState: Sized + Send + Sync + 'static {
     fn init(resources: &mut Resources) -> Self;
 
     fn update_access(&self, resources: &Resources, access: &mut ResourceAccess);
+
+    /// Called after the access of the system was validated, when none of its
+    /// parameters conflict with each other.
+    #[inline]
+    fn access_validated(&mut self) {}
+
+    /// readable name of the parameter (used for reporting access conflicts)
+    fn type_name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(std::any::type_name::<Self>())
+    }
 }
 
 pub trait SystemDataFetch<'r> {
@@ -53,7 +65,18 @@ macro_rules! impl_system_param {
 
             #[inline]
             fn update_access(&self, _resources: &Resources, _access: &mut ResourceAccess) {
-                $($(self.$index.update_access(_resources, _access);)+)?
+                $(
+                    _access.extend_checked(Some(_resources), [$({
+                        let mut access = ResourceAccess::new();
+                        self.$index.update_access(_resources, &mut access);
+                        (self.$index.type_name(), access)
+                    }),+]);
+                )?
+            }
+
+            #[inline]
+            fn access_validated(&mut self) {
+                $($(self.$index.access_validated();)+)?
             }
         }
 
@@ -20,6 +20,11 @@ pub unsafe trait System<Args = ()>: Send + Sync {
 
     fn update_access(&self, resources: &Resources, access: &mut ResourceAccess);
 
+    /// Called after the access of the system was validated, when none of its
+    /// parameters conflict with each other.
+    #[inline]
+    fn access_validated(&mut self) {}
+
     fn type_name(&self) -> &'static str {
         std::any::type_name::<Self>()
     }
@@ -159,6 +164,16 @@ impl SystemDescriptor {
             SystemVariant::Concurrent(ref mut system, ref mut access) => {
                 system.init(resources);
                 system.update_access(resources, access);
+                if !access.conflicts().is_empty() {
+                    let conflicts: Vec<_> =
+                        access.conflicts().iter().map(ToString::to_string).collect();
+                    panic!(
+                        "system `{}` has conflicting parameters: {}",
+                        system.type_name(),
+                        conflicts.join("; ")
+                    );
+                }
+                system.access_validated();
             }
         }
         self.is_initialized = true;
@@ -232,6 +247,11 @@ where
         self.as_ref().update_access(resources, access)
     }
 
+    #[inline]
+    fn access_validated(&mut self) {
+        self.as_mut().access_validated()
+    }
+
     #[inline]
     fn type_name(&self) -> &'static str {
         self.as_ref().type_name()
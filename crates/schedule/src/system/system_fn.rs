@@ -93,6 +93,12 @@ where
         state.update_access(resources, access);
     }
 
+    #[inline]
+    fn access_validated(&mut self) {
+        let state = self.state.as_mut().expect("not initialized");
+        state.access_validated();
+    }
+
     fn type_name(&self) -> &'static str {
         std::any::type_name::<F>()
     }
@@ -281,6 +287,17 @@ mod tests {
         assert_eq!(21, resources.get_mut::<A>().unwrap().0);
     }
 
+    #[test]
+    #[should_panic(expected = "is accessed by `&mut ")]
+    fn test_system_fn_conflicting_params() {
+        fn sys(_a: &mut A, _b: &A) {}
+
+        let mut resources = Resources::new();
+        resources.insert(A(11));
+
+        resources.run(sys);
+    }
+
     #[test]
     fn test_system_with_arg() {
         let value = Arc::new(std::sync::atomic::AtomicUsize::new(0));